@@ -0,0 +1,503 @@
+/// the append-only log; each worker logs its writes per route and replays the log on start
+use crate::cache::backend::CacheBackend;
+use crate::cache::store::Entry;
+use async_channel::{bounded, Receiver};
+use async_std::fs::{self, File, OpenOptions};
+use async_std::io::{BufWriter, WriteExt};
+use async_std::task;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// when the log is flushed to disk
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// after every write, before the response; the safest and slowest
+    Always,
+    /// at most once a second; a crash loses up to a second of writes
+    #[default]
+    EverySecond,
+    /// leave it to the operating system
+    Never,
+}
+
+/// append-only log options
+#[derive(Debug, Clone)]
+pub struct AofConfig {
+    /// the directory for the log files, one per route
+    pub dir: PathBuf,
+    pub fsync: FsyncPolicy,
+    /// the log is not rewritten until it reaches this size
+    pub rewrite_min_bytes: u64,
+    /// rewrite when the log has grown by this factor since the last rewrite
+    pub rewrite_growth: u64,
+}
+
+impl AofConfig {
+    pub fn new(dir: impl AsRef<Path>) -> AofConfig {
+        AofConfig {
+            dir: dir.as_ref().to_path_buf(),
+            fsync: FsyncPolicy::default(),
+            rewrite_min_bytes: 1024 * 1024,
+            rewrite_growth: 2,
+        }
+    }
+
+    /// the log file for the worker at the route
+    pub fn path(&self, route: usize) -> PathBuf {
+        self.dir.join(format!("worker-{:04}.aof", route))
+    }
+}
+
+/// a line in the log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Record {
+    Set {
+        key: String,
+        value: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<u64>,
+    },
+    Remove {
+        key: String,
+    },
+}
+
+impl Record {
+    pub fn set(key: &str, value: &str, expires_at: Option<u64>) -> Record {
+        Record::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+            expires_at,
+        }
+    }
+
+    pub fn remove(key: &str) -> Record {
+        Record::Remove {
+            key: key.to_string(),
+        }
+    }
+
+    /// apply the record to the store
+    pub async fn apply(self, store: &mut dyn CacheBackend, now: u64) -> io::Result<()> {
+        match self {
+            Record::Set {
+                key,
+                value,
+                expires_at,
+            } => {
+                store
+                    .insert(key, Entry::new(value, expires_at), now)
+                    .await?;
+            }
+            Record::Remove { key } => {
+                store.remove(&key, now).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn encode(record: &Record) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// a rewrite in progress; holds the writes made since it started
+#[derive(Debug)]
+struct Rewrite {
+    done: Receiver<io::Result<()>>,
+    pending: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Aof {
+    path: PathBuf,
+    file: BufWriter<File>,
+    fsync: FsyncPolicy,
+    last_sync: Instant,
+    size: u64,
+    base_size: u64,
+    rewrite_min_bytes: u64,
+    rewrite_growth: u64,
+    rewrite: Option<Rewrite>,
+    /// no rewrites once the log is handed to a replacement worker
+    handed_over: bool,
+}
+
+impl Aof {
+    /// open the route's log and return it with the records to replay.  A torn last line from a
+    /// crash in the middle of a write is dropped.
+    pub async fn open(config: &AofConfig, route: usize) -> io::Result<(Aof, Vec<Record>)> {
+        fs::create_dir_all(&config.dir).await?;
+        let path = config.path(route);
+
+        let records = match fs::read_to_string(&path).await {
+            Ok(text) => parse(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        let aof = Aof::append_to(config, path).await?;
+        Ok((aof, records))
+    }
+
+    /// start the route's log over with the entries, replacing the file without reading it; used by
+    /// a worker that takes over from a recycled worker
+    pub async fn create(
+        config: &AofConfig,
+        route: usize,
+        entries: Vec<(String, Entry)>,
+    ) -> io::Result<Aof> {
+        fs::create_dir_all(&config.dir).await?;
+        let path = config.path(route);
+        let tmp = path.with_extension("aof.handoff");
+        write_log(&tmp, entries).await?;
+        fs::rename(&tmp, &path).await?;
+
+        Aof::append_to(config, path).await
+    }
+
+    async fn append_to(config: &AofConfig, path: PathBuf) -> io::Result<Aof> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let size = file.metadata().await?.len();
+
+        Ok(Aof {
+            path,
+            file: BufWriter::new(file),
+            fsync: config.fsync,
+            last_sync: Instant::now(),
+            size,
+            base_size: size,
+            rewrite_min_bytes: config.rewrite_min_bytes,
+            rewrite_growth: config.rewrite_growth.max(1),
+            rewrite: None,
+            handed_over: false,
+        })
+    }
+
+    /// the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the size of the log in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// append the records; flushed to disk according to the fsync policy
+    pub async fn append(&mut self, records: &[Record]) -> io::Result<()> {
+        let mut lines = vec![];
+        for record in records {
+            lines.extend_from_slice(&encode(record)?);
+        }
+
+        self.file.write_all(&lines).await?;
+        self.size += lines.len() as u64;
+        // a write after the hand over means the worker was not replaced after all
+        self.handed_over = false;
+
+        if let Some(rewrite) = self.rewrite.as_mut() {
+            rewrite.pending.extend_from_slice(&lines);
+        }
+
+        match self.fsync {
+            FsyncPolicy::Always => self.sync().await,
+            FsyncPolicy::EverySecond | FsyncPolicy::Never => self.file.flush().await,
+        }
+    }
+
+    /// the periodic work: the once a second fsync and finishing a background rewrite
+    pub async fn tick(&mut self) -> io::Result<()> {
+        if self.fsync == FsyncPolicy::EverySecond
+            && self.last_sync.elapsed() >= Duration::from_secs(1)
+        {
+            self.sync().await?;
+        }
+
+        let done = match self.rewrite.as_ref() {
+            Some(rewrite) => rewrite.done.try_recv().ok(),
+            None => None,
+        };
+
+        if let Some(result) = done {
+            let rewrite = self.rewrite.take().expect("rewrite is in progress");
+            match result {
+                Ok(()) => self.finish_rewrite(rewrite.pending).await?,
+                Err(e) => error!("aof rewrite of {:?} failed: {}", self.path, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// return true if the log has grown enough to compact
+    pub fn needs_rewrite(&self) -> bool {
+        self.rewrite.is_none()
+            && !self.handed_over
+            && self.size >= self.rewrite_min_bytes
+            && self.size >= self.base_size.saturating_mul(self.rewrite_growth)
+    }
+
+    /// return true while a rewrite is running
+    pub fn is_rewriting(&self) -> bool {
+        self.rewrite.is_some()
+    }
+
+    /// start writing the entries to a new log in the background
+    pub fn start_rewrite(&mut self, entries: Vec<(String, Entry)>) {
+        let (tx, rx) = bounded(1);
+        let tmp = self.rewrite_path();
+        info!("aof rewrite of {:?}, {} entries", self.path, entries.len());
+
+        task::spawn(async move {
+            let _ = tx.send(write_log(&tmp, entries).await).await;
+        });
+
+        self.rewrite = Some(Rewrite {
+            done: rx,
+            pending: vec![],
+        });
+    }
+
+    /// wait for a rewrite in progress to finish
+    pub async fn wait_rewrite(&mut self) -> io::Result<()> {
+        if let Some(rewrite) = self.rewrite.as_ref() {
+            let _ = rewrite.done.recv().await;
+            let rewrite = self.rewrite.take().expect("rewrite is in progress");
+            self.finish_rewrite(rewrite.pending).await?;
+        }
+
+        Ok(())
+    }
+
+    /// get the log ready for a replacement worker to take over: finish a rewrite in progress,
+    /// sync and start no more rewrites, so the replacement's log is never renamed over
+    pub async fn hand_over(&mut self) -> io::Result<()> {
+        self.wait_rewrite().await?;
+        self.sync().await?;
+        self.handed_over = true;
+        Ok(())
+    }
+
+    /// flush and sync the log
+    pub async fn sync(&mut self) -> io::Result<()> {
+        self.file.flush().await?;
+        self.file.get_ref().sync_data().await?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    fn rewrite_path(&self) -> PathBuf {
+        self.path.with_extension("aof.rewrite")
+    }
+
+    /// add the writes made during the rewrite and swap the new log in
+    async fn finish_rewrite(&mut self, pending: Vec<u8>) -> io::Result<()> {
+        self.file.flush().await?;
+        let tmp = self.rewrite_path();
+
+        let mut file = OpenOptions::new().append(true).open(&tmp).await?;
+        file.write_all(&pending).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp, &self.path).await?;
+
+        let file = OpenOptions::new().append(true).open(&self.path).await?;
+        self.size = file.metadata().await?.len();
+        self.base_size = self.size;
+        self.file = BufWriter::new(file);
+        info!(
+            "aof rewrite complete, {:?} is {} bytes",
+            self.path, self.size
+        );
+
+        Ok(())
+    }
+}
+
+fn parse(text: &str) -> io::Result<Vec<Record>> {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let mut records = Vec::with_capacity(lines.len());
+
+    for (n, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(e) if n + 1 == lines.len() && !text.ends_with('\n') => {
+                warn!("drop torn aof line: {}", e);
+            }
+            Err(e) => {
+                let msg = format!("aof line {}: {}", n + 1, e);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// write the entries as a fresh log
+async fn write_log(path: &Path, entries: Vec<(String, Entry)>) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path).await?);
+    for (key, entry) in entries.iter() {
+        let record = Record::set(key, &entry.value, entry.expires_at);
+        file.write_all(&encode(&record)?).await?;
+    }
+
+    file.flush().await?;
+    file.get_ref().sync_all().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::eviction::EvictionPolicy;
+    use crate::cache::store::MemoryStore;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("worker-lib-aof-{}", fastrand::u64(..)))
+    }
+
+    async fn replay(records: Vec<Record>) -> MemoryStore {
+        let mut store = MemoryStore::new(None, None, EvictionPolicy::Lru);
+        for record in records {
+            record.apply(&mut store, 0).await.unwrap();
+        }
+
+        store
+    }
+
+    #[test]
+    fn append_replay() {
+        async_std::task::block_on(async move {
+            let config = AofConfig {
+                fsync: FsyncPolicy::Always,
+                ..AofConfig::new(temp_dir())
+            };
+            let (mut aof, records) = Aof::open(&config, 3).await.unwrap();
+            assert!(records.is_empty());
+            assert_eq!(aof.path(), config.path(3).as_path());
+
+            let records = [
+                Record::set("a", "1", Some(500)),
+                Record::set("b", "2", None),
+            ];
+            aof.append(&records).await.unwrap();
+            aof.append(&[Record::remove("b")]).await.unwrap();
+            drop(aof);
+
+            // a torn write at the end is dropped
+            let mut text = fs::read_to_string(config.path(3)).await.unwrap();
+            text.push_str("{\"op\":\"set\",\"key\":\"c\"");
+            fs::write(config.path(3), &text).await.unwrap();
+
+            let (_, records) = Aof::open(&config, 3).await.unwrap();
+            assert_eq!(records.len(), 3);
+            let mut store = replay(records).await;
+            assert_eq!(store.keys(0), vec!["a".to_string()]);
+            assert_eq!(store.get("a", 0), Some("1".to_string()));
+            assert_eq!(store.get("a", 500), None);
+
+            // damage in the middle is an error
+            fs::write(config.path(3), format!("bad\n{}", text))
+                .await
+                .unwrap();
+            assert!(Aof::open(&config, 3).await.is_err());
+
+            let _ = fs::remove_dir_all(&config.dir).await;
+        });
+    }
+
+    #[test]
+    fn rewrite() {
+        async_std::task::block_on(async move {
+            let config = AofConfig {
+                fsync: FsyncPolicy::Never,
+                rewrite_min_bytes: 200,
+                ..AofConfig::new(temp_dir())
+            };
+            let (mut aof, _) = Aof::open(&config, 0).await.unwrap();
+
+            let entry = Entry::new("value".to_string(), None);
+            for _ in 0..20 {
+                aof.append(&[Record::set("same", "value", None)])
+                    .await
+                    .unwrap();
+            }
+            assert!(aof.needs_rewrite());
+            let before = aof.size();
+
+            aof.start_rewrite(vec![("same".to_string(), entry.clone())]);
+            assert!(!aof.needs_rewrite());
+
+            // writes during the rewrite are kept
+            aof.append(&[Record::set("during", "value", None)])
+                .await
+                .unwrap();
+            aof.wait_rewrite().await.unwrap();
+            assert!(!aof.is_rewriting());
+            assert!(aof.size() < before / 5);
+
+            aof.append(&[Record::remove("same")]).await.unwrap();
+            aof.sync().await.unwrap();
+
+            let (_, records) = Aof::open(&config, 0).await.unwrap();
+            assert_eq!(records.len(), 3);
+            let store = replay(records).await;
+            assert_eq!(store.keys(0), vec!["during".to_string()]);
+
+            let _ = fs::remove_dir_all(&config.dir).await;
+        });
+    }
+
+    #[test]
+    fn hand_over() {
+        async_std::task::block_on(async move {
+            let config = AofConfig {
+                fsync: FsyncPolicy::Never,
+                rewrite_min_bytes: 100,
+                ..AofConfig::new(temp_dir())
+            };
+            let (mut old, _) = Aof::open(&config, 1).await.unwrap();
+            for n in 0..10 {
+                let key = format!("key-{}", n);
+                old.append(&[Record::set(&key, "value", None)])
+                    .await
+                    .unwrap();
+            }
+            assert!(old.needs_rewrite());
+            old.hand_over().await.unwrap();
+            assert!(!old.needs_rewrite());
+
+            // the replacement's log starts over from its entries
+            let entry = Entry::new("value".to_string(), None);
+            let entries = vec![("key-3".to_string(), entry)];
+            let mut new = Aof::create(&config, 1, entries).await.unwrap();
+            new.append(&[Record::set("after", "value", None)])
+                .await
+                .unwrap();
+            new.sync().await.unwrap();
+
+            let (_, records) = Aof::open(&config, 1).await.unwrap();
+            let store = replay(records).await;
+            let mut keys = store.keys(0);
+            keys.sort();
+            assert_eq!(keys, vec!["after".to_string(), "key-3".to_string()]);
+
+            // a write after the hand over means the old worker stays
+            old.append(&[Record::remove("key-3")]).await.unwrap();
+            assert!(old.needs_rewrite());
+
+            let _ = fs::remove_dir_all(&config.dir).await;
+        });
+    }
+}
@@ -0,0 +1,215 @@
+/// the store behind each cache worker, owned by the worker's handler loop
+use crate::cache::file::{FileBackend, FileConfig};
+use crate::cache::pattern::KeyPattern;
+use crate::cache::redis::{RedisBackend, RedisConfig};
+use crate::cache::scan;
+use crate::cache::store::{Entry, MemoryStore, Removal};
+use crate::cache::tiered::TieredBackend;
+use crate::cache::worker::{CacheConfig, KeyTtl};
+use crate::worker::TierStats;
+use futures::future::BoxFuture;
+use std::fmt::Debug;
+use std::io;
+use std::time::Duration;
+
+/// the future returned by the backend operations
+pub type BackendFuture<'a, T> = BoxFuture<'a, io::Result<T>>;
+
+/// the operations the cache worker needs from a store.  Times are unix epoch milliseconds from
+/// the worker's clock.
+pub trait CacheBackend: Debug + Send {
+    /// return the live value for the key
+    fn get<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>>;
+
+    /// store the entry; return the previous live value
+    fn insert(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, Option<String>>;
+
+    /// remove the entry; return the value if it was live
+    fn remove<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>>;
+
+    /// return the time to live for the key
+    fn ttl<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, KeyTtl>;
+
+    /// remove the key's expiry; return true if it had one
+    fn persist<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, bool>;
+
+    /// remove and return the live entry
+    fn take<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<Entry>>;
+
+    /// store the entry unless the key already has a live value; return true if it was stored
+    fn put(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, bool>;
+
+    /// return all live keys
+    fn keys(&mut self, now: u64) -> BackendFuture<'_, Vec<String>>;
+
+    /// return up to `count` live keys that match the pattern and sort after the `after` key, in
+    /// sorted order.  The default filters all keys.
+    fn scan<'a>(
+        &'a mut self,
+        pattern: &'a KeyPattern,
+        after: Option<&'a str>,
+        count: usize,
+        now: u64,
+    ) -> BackendFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            let keys = self.keys(now).await?;
+            Ok(scan::page(
+                keys.iter().map(String::as_str),
+                pattern,
+                after,
+                count,
+            ))
+        })
+    }
+
+    /// return a copy of all live entries
+    fn entries(&mut self, now: u64) -> BackendFuture<'_, Vec<(String, Entry)>>;
+
+    /// the number of stored entries
+    fn len(&mut self, now: u64) -> BackendFuture<'_, usize>;
+
+    fn is_empty(&mut self, now: u64) -> BackendFuture<'_, bool> {
+        Box::pin(async move { Ok(self.len(now).await? == 0) })
+    }
+
+    /// remove expired entries; return the number removed
+    fn sweep(&mut self, now: u64) -> BackendFuture<'_, usize>;
+
+    /// the number of entries removed to stay within a size limit
+    fn evictions(&self) -> u64;
+
+    /// write buffered changes through to the backing store; return the number written
+    fn flush(&mut self, _now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(futures::future::ready(Ok(0)))
+    }
+
+    /// how often the handler should call `flush`; None if nothing is buffered
+    fn flush_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// hit and miss counts for each level of a tiered store
+    fn stats(&self) -> Vec<TierStats> {
+        vec![]
+    }
+
+    /// the keys the store expired or evicted on its own since the last call.  Stores that
+    /// expire entries out of sight of the worker, like Redis, report nothing.
+    fn drain_removals(&mut self) -> Vec<Removal> {
+        vec![]
+    }
+}
+
+/// which store the cache workers use
+#[derive(Debug, Default, Clone)]
+pub enum BackendConfig {
+    /// entries live in the worker; the size limits and eviction policy apply
+    #[default]
+    Memory,
+    /// entries live in Redis under a prefix for each worker route.  Redis expires entries
+    /// itself; the size limits are left to the server's maxmemory policy.
+    Redis(RedisConfig),
+    /// entries live in files in a directory for each worker route
+    File(FileConfig),
+}
+
+impl BackendConfig {
+    /// create the backend for the worker; with a second level configured the backend is the
+    /// first level of a tiered store
+    pub async fn open(config: &CacheConfig) -> io::Result<Box<dyn CacheBackend>> {
+        let backend = config.backend.connect(config).await?;
+        match config.l2.as_ref() {
+            Some(tier) => {
+                let l2 = tier.backend.connect(config).await?;
+                Ok(Box::new(TieredBackend::new(backend, l2, tier)))
+            }
+            None => Ok(backend),
+        }
+    }
+
+    async fn connect(&self, config: &CacheConfig) -> io::Result<Box<dyn CacheBackend>> {
+        match self {
+            BackendConfig::Memory => Ok(Box::new(MemoryStore::new(
+                config.max_entries,
+                config.max_bytes,
+                config.eviction,
+            ))),
+            BackendConfig::Redis(redis) => {
+                let backend = RedisBackend::connect(redis, config.route).await?;
+                Ok(Box::new(backend))
+            }
+            BackendConfig::File(file) => {
+                let backend = FileBackend::open(file, config.route).await?;
+                Ok(Box::new(backend))
+            }
+        }
+    }
+}
+
+fn ready<'a, T: Send + 'a>(value: T) -> BackendFuture<'a, T> {
+    Box::pin(futures::future::ready(Ok(value)))
+}
+
+impl CacheBackend for MemoryStore {
+    fn get<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>> {
+        ready(MemoryStore::get(self, key, now))
+    }
+
+    fn insert(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, Option<String>> {
+        ready(MemoryStore::insert(self, key, entry, now))
+    }
+
+    fn remove<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>> {
+        ready(MemoryStore::remove(self, key, now))
+    }
+
+    fn ttl<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, KeyTtl> {
+        ready(MemoryStore::ttl(self, key, now))
+    }
+
+    fn persist<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, bool> {
+        ready(MemoryStore::persist(self, key, now))
+    }
+
+    fn take<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<Entry>> {
+        ready(MemoryStore::take(self, key, now))
+    }
+
+    fn put(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, bool> {
+        ready(MemoryStore::put(self, key, entry, now))
+    }
+
+    fn keys(&mut self, now: u64) -> BackendFuture<'_, Vec<String>> {
+        ready(MemoryStore::keys(self, now))
+    }
+
+    fn scan<'a>(
+        &'a mut self,
+        pattern: &'a KeyPattern,
+        after: Option<&'a str>,
+        count: usize,
+        now: u64,
+    ) -> BackendFuture<'a, Vec<String>> {
+        ready(MemoryStore::scan(self, pattern, after, count, now))
+    }
+
+    fn entries(&mut self, now: u64) -> BackendFuture<'_, Vec<(String, Entry)>> {
+        ready(MemoryStore::entries(self, now))
+    }
+
+    fn len(&mut self, _now: u64) -> BackendFuture<'_, usize> {
+        ready(MemoryStore::len(self))
+    }
+
+    fn sweep(&mut self, now: u64) -> BackendFuture<'_, usize> {
+        ready(MemoryStore::sweep(self, now))
+    }
+
+    fn evictions(&self) -> u64 {
+        MemoryStore::evictions(self)
+    }
+
+    fn drain_removals(&mut self) -> Vec<Removal> {
+        MemoryStore::drain_removals(self)
+    }
+}
@@ -0,0 +1,92 @@
+/// time sources for entry expiry, in milliseconds since the unix epoch
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub trait Clock: Debug + Send + Sync {
+    /// return the current time in milliseconds since the unix epoch
+    fn now_millis(&self) -> u64;
+}
+
+/// the expiry time of an entry set now with the ttl; a ttl too large to represent never expires
+pub fn expires_at(now: u64, ttl: Duration) -> u64 {
+    now.saturating_add(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// the wall clock; used by default
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+/// a clock that only moves when told to; clones share the same time
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    millis: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// create a manual clock starting at the given time
+    pub fn new(millis: u64) -> ManualClock {
+        ManualClock {
+            millis: Arc::new(AtomicU64::new(millis)),
+        }
+    }
+
+    /// move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        self.millis
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// set the clock to the given time
+    pub fn set(&self, millis: u64) {
+        self.millis.store(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.millis.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_clock() {
+        let clock = SystemClock;
+        let t0 = clock.now_millis();
+        assert!(t0 > 1_600_000_000_000);
+        assert!(clock.now_millis() >= t0);
+    }
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new(1_000);
+        let shared = clock.clone();
+        assert_eq!(clock.now_millis(), 1_000);
+
+        shared.advance(Duration::from_secs(5));
+        assert_eq!(clock.now_millis(), 6_000);
+
+        clock.set(42);
+        assert_eq!(shared.now_millis(), 42);
+    }
+
+    #[test]
+    fn expiry() {
+        assert_eq!(expires_at(1_000, Duration::from_secs(2)), 3_000);
+        assert_eq!(expires_at(1_000, Duration::MAX), u64::MAX);
+        assert_eq!(expires_at(u64::MAX - 1, Duration::from_secs(1)), u64::MAX);
+    }
+}
@@ -0,0 +1,239 @@
+/// keyspace change notifications published by the workers to matching subscribers
+use crate::cache::pattern::KeyPattern;
+use crate::cache::store::Removal;
+use async_channel::{Receiver, Sender, TrySendError};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// a change to a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheEvent {
+    /// the key was stored with the value
+    Set { key: String, value: String },
+    /// the key was removed by a request
+    Remove { key: String },
+    /// the key's ttl ran out
+    Expire { key: String },
+    /// the key was removed to stay within a size limit
+    Evict { key: String },
+}
+
+impl CacheEvent {
+    /// the key that changed
+    pub fn key(&self) -> &str {
+        match self {
+            CacheEvent::Set { key, .. }
+            | CacheEvent::Remove { key }
+            | CacheEvent::Expire { key }
+            | CacheEvent::Evict { key } => key,
+        }
+    }
+}
+
+impl From<Removal> for CacheEvent {
+    fn from(removal: Removal) -> Self {
+        match removal {
+            Removal::Expired(key) => CacheEvent::Expire { key },
+            Removal::Evicted(key) => CacheEvent::Evict { key },
+        }
+    }
+}
+
+/// what happens when a subscriber's buffer is full
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SlowPolicy {
+    /// discard the oldest buffered event to make room for the new one
+    #[default]
+    DropOldest,
+    /// close the subscriber's channel; it receives the buffered events, then an error
+    Disconnect,
+}
+
+/// subscriber options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscribeOptions {
+    /// the number of events buffered for the subscriber
+    pub capacity: usize,
+    pub policy: SlowPolicy,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        SubscribeOptions {
+            capacity: 100,
+            policy: SlowPolicy::default(),
+        }
+    }
+}
+
+struct Subscriber {
+    pattern: KeyPattern,
+    policy: SlowPolicy,
+    tx: Sender<CacheEvent>,
+    /// kept to pop the oldest event when the buffer is full
+    rx: Receiver<CacheEvent>,
+}
+
+impl Subscriber {
+    /// deliver the event; return false once the subscriber is gone
+    fn send(&self, event: &CacheEvent) -> bool {
+        // the only receiver left is our own
+        if self.tx.receiver_count() <= 1 {
+            return false;
+        }
+
+        let mut event = event.clone();
+        loop {
+            match self.tx.try_send(event) {
+                Ok(()) => return true,
+                Err(TrySendError::Closed(_)) => return false,
+                Err(TrySendError::Full(_)) if self.policy == SlowPolicy::Disconnect => {
+                    self.tx.close();
+                    return false;
+                }
+                Err(TrySendError::Full(e)) => {
+                    let _ = self.rx.try_recv();
+                    event = e;
+                }
+            }
+        }
+    }
+}
+
+/// the subscribers for a pool of cache workers
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<RwLock<Vec<Subscriber>>>,
+}
+
+impl EventBus {
+    /// register a subscriber for the keys that match the pattern
+    pub fn subscribe(
+        &self,
+        pattern: KeyPattern,
+        options: SubscribeOptions,
+    ) -> Receiver<CacheEvent> {
+        let (tx, rx) = async_channel::bounded(options.capacity.max(1));
+        let subscriber = Subscriber {
+            pattern,
+            policy: options.policy,
+            tx,
+            rx: rx.clone(),
+        };
+
+        self.subscribers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(subscriber);
+
+        rx
+    }
+
+    /// the number of registered subscribers; dropped subscribers are counted until the next event
+    pub fn len(&self) -> usize {
+        self.subscribers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// send the event to the matching subscribers.  The event is only built when there is
+    /// someone to send it to.
+    pub fn publish(&self, event: impl FnOnce() -> CacheEvent) {
+        if self.is_empty() {
+            return;
+        }
+
+        let event = event();
+        let gone = {
+            let subscribers = self.subscribers.read().unwrap_or_else(|e| e.into_inner());
+            subscribers
+                .iter()
+                .filter(|s| s.pattern.matches(event.key()))
+                .filter(|s| !s.send(&event))
+                .count()
+                > 0
+        };
+
+        // drop the closed and abandoned subscribers
+        if gone {
+            self.subscribers
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .retain(|s| !s.tx.is_closed() && s.tx.receiver_count() > 1);
+        }
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EventBus({} subscribers)", self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(key: &str, value: &str) -> CacheEvent {
+        CacheEvent::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let bus = EventBus::default();
+        let options = SubscribeOptions {
+            capacity: 2,
+            policy: SlowPolicy::DropOldest,
+        };
+        let rx = bus.subscribe(KeyPattern::new("user:*"), options);
+
+        for n in 0..5 {
+            bus.publish(|| set(&format!("user:{}", n), "v"));
+        }
+        bus.publish(|| set("other", "v"));
+
+        assert_eq!(rx.try_recv(), Ok(set("user:3", "v")));
+        assert_eq!(rx.try_recv(), Ok(set("user:4", "v")));
+        assert!(rx.try_recv().is_err());
+
+        // a dropped subscriber is removed on the next matching event
+        drop(rx);
+        assert_eq!(bus.len(), 1);
+        bus.publish(|| set("user:5", "v"));
+        assert!(bus.is_empty());
+    }
+
+    #[test]
+    fn disconnect() {
+        let bus = EventBus::default();
+        let options = SubscribeOptions {
+            capacity: 2,
+            policy: SlowPolicy::Disconnect,
+        };
+        let slow = bus.subscribe(KeyPattern::all(), options);
+        let other = bus.subscribe(KeyPattern::all(), SubscribeOptions::default());
+
+        for n in 0..3 {
+            bus.publish(|| CacheEvent::Remove {
+                key: format!("k{}", n),
+            });
+        }
+
+        // the buffered events are still delivered, then the channel is closed
+        assert_eq!(slow.try_recv().unwrap().key(), "k0");
+        assert_eq!(slow.try_recv().unwrap().key(), "k1");
+        assert!(slow.try_recv().is_err());
+        assert!(slow.is_closed());
+
+        assert_eq!(other.len(), 3);
+        assert_eq!(bus.len(), 1);
+    }
+}
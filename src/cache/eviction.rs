@@ -0,0 +1,166 @@
+/// eviction policies for bounded cache workers
+use hashbrown::HashMap;
+use std::collections::BTreeSet;
+
+/// decides which entry is removed when a bounded cache is full
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// the least recently used entry
+    #[default]
+    Lru,
+    /// the least frequently used entry; ties go to the least recently used
+    Lfu,
+    /// a random entry
+    Random,
+    /// the oldest entry; reads and updates do not change the order
+    Fifo,
+}
+
+type Rank = (u64, u64);
+
+#[derive(Debug, Default)]
+pub struct Evictor {
+    policy: EvictionPolicy,
+    tick: u64,
+    ranks: HashMap<String, Rank>,
+    order: BTreeSet<(Rank, String)>,
+}
+
+impl Evictor {
+    pub fn new(policy: EvictionPolicy) -> Evictor {
+        Evictor {
+            policy,
+            ..Evictor::default()
+        }
+    }
+
+    /// record a read or write of the key
+    pub fn touch(&mut self, key: &str) {
+        self.tick += 1;
+
+        let prev = self.ranks.get(key).copied();
+        let rank = match (self.policy, prev) {
+            (EvictionPolicy::Lru, _) => (self.tick, 0),
+            (EvictionPolicy::Lfu, Some((hits, _))) => (hits + 1, self.tick),
+            (EvictionPolicy::Lfu, None) => (1, self.tick),
+            (EvictionPolicy::Random, None) => (fastrand::u64(..), self.tick),
+            (EvictionPolicy::Fifo, None) => (self.tick, 0),
+            (EvictionPolicy::Random, Some(_)) | (EvictionPolicy::Fifo, Some(_)) => return,
+        };
+
+        if let Some(prev) = prev {
+            self.order.remove(&(prev, key.to_string()));
+        }
+
+        self.order.insert((rank, key.to_string()));
+        self.ranks.insert(key.to_string(), rank);
+    }
+
+    /// stop tracking the key
+    pub fn remove(&mut self, key: &str) {
+        if let Some(rank) = self.ranks.remove(key) {
+            self.order.remove(&(rank, key.to_string()));
+        }
+    }
+
+    /// return the next key to evict, never the `keep` key
+    pub fn victim(&self, keep: &str) -> Option<String> {
+        self.order
+            .iter()
+            .map(|(_, key)| key)
+            .find(|key| key.as_str() != keep)
+            .cloned()
+    }
+
+    /// the number of tracked keys
+    pub fn len(&self) -> usize {
+        self.ranks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evictor(policy: EvictionPolicy) -> Evictor {
+        let mut evictor = Evictor::new(policy);
+        for key in ["a", "b", "c"] {
+            evictor.touch(key);
+        }
+
+        evictor
+    }
+
+    #[test]
+    fn lru() {
+        let mut evictor = evictor(EvictionPolicy::Lru);
+        assert_eq!(evictor.victim(""), Some("a".to_string()));
+
+        evictor.touch("a");
+        assert_eq!(evictor.victim(""), Some("b".to_string()));
+        assert_eq!(evictor.victim("b"), Some("c".to_string()));
+
+        evictor.remove("b");
+        assert_eq!(evictor.victim(""), Some("c".to_string()));
+        assert_eq!(evictor.len(), 2);
+    }
+
+    #[test]
+    fn lfu() {
+        let mut evictor = evictor(EvictionPolicy::Lfu);
+        evictor.touch("a");
+        evictor.touch("a");
+        evictor.touch("c");
+
+        // b has the fewest hits
+        assert_eq!(evictor.victim(""), Some("b".to_string()));
+
+        // c and b tie, c is the least recent
+        evictor.touch("b");
+        assert_eq!(evictor.victim(""), Some("c".to_string()));
+        assert_eq!(evictor.victim("c"), Some("b".to_string()));
+    }
+
+    #[test]
+    fn fifo() {
+        let mut evictor = evictor(EvictionPolicy::Fifo);
+        evictor.touch("a");
+        evictor.touch("a");
+        assert_eq!(evictor.victim(""), Some("a".to_string()));
+
+        evictor.remove("a");
+        evictor.touch("a");
+        assert_eq!(evictor.victim(""), Some("b".to_string()));
+    }
+
+    #[test]
+    fn random() {
+        let mut evictor = evictor(EvictionPolicy::Random);
+        let victim = evictor.victim("").expect("should have a victim");
+        assert!(["a", "b", "c"].contains(&victim.as_str()));
+
+        // reads do not change the order
+        evictor.touch("a");
+        evictor.touch("b");
+        assert_eq!(evictor.victim(""), Some(victim.to_string()));
+
+        evictor.remove(&victim);
+        assert_eq!(evictor.len(), 2);
+        assert_ne!(evictor.victim(""), Some(victim));
+    }
+
+    #[test]
+    fn empty() {
+        let evictor = Evictor::new(EvictionPolicy::Lru);
+        assert!(evictor.is_empty());
+        assert_eq!(evictor.victim(""), None);
+
+        let mut evictor = Evictor::new(EvictionPolicy::Lru);
+        evictor.touch("only");
+        assert_eq!(evictor.victim("only"), None);
+    }
+}
@@ -0,0 +1,293 @@
+/// the file cache backend; one json file per key in a directory for each route
+use crate::cache::backend::{BackendFuture, CacheBackend};
+use crate::cache::store::Entry;
+use crate::cache::worker::KeyTtl;
+use crate::supervisor::routing::hash_key;
+use async_std::fs;
+use async_std::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// keys with a longer hex name are shortened and made unique with the key's hash
+const MAX_NAME_LEN: usize = 200;
+
+/// file backend options
+#[derive(Debug, Clone)]
+pub struct FileConfig {
+    /// the directory for the worker directories
+    pub dir: PathBuf,
+}
+
+impl FileConfig {
+    pub fn new(dir: impl AsRef<Path>) -> FileConfig {
+        FileConfig {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// the directory for the worker at the route
+    pub fn path(&self, route: usize) -> PathBuf {
+        self.dir.join(format!("worker-{:04}", route))
+    }
+}
+
+/// the contents of an entry's file
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    key: String,
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    pub async fn open(config: &FileConfig, route: usize) -> io::Result<FileBackend> {
+        let dir = config.path(route);
+        fs::create_dir_all(&dir).await?;
+
+        Ok(FileBackend { dir })
+    }
+
+    /// the key's file; the key is hex encoded so any key makes a valid file name
+    fn path(&self, key: &str) -> PathBuf {
+        let mut name: String = key.bytes().map(|b| format!("{:02x}", b)).collect();
+        if name.len() > MAX_NAME_LEN {
+            name.truncate(MAX_NAME_LEN - 17);
+            name = format!("{}-{:016x}", name, hash_key(key));
+        }
+
+        self.dir.join(format!("{}.json", name))
+    }
+
+    async fn read(path: &Path) -> io::Result<Option<Record>> {
+        match fs::read_to_string(path).await {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// the live entry for the key; an expired file is removed
+    async fn load(&self, key: &str, now: u64) -> io::Result<Option<Entry>> {
+        let path = self.path(key);
+        let record = match FileBackend::read(&path).await? {
+            Some(record) if record.key == key => record,
+            _ => return Ok(None),
+        };
+
+        let entry = Entry::new(record.value, record.expires_at);
+        if entry.is_expired(now) {
+            FileBackend::delete(&path).await?;
+            return Ok(None);
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// write to a temporary file then rename so a reader never sees part of an entry
+    async fn store(&self, key: &str, entry: &Entry) -> io::Result<()> {
+        let record = Record {
+            key: key.to_string(),
+            value: entry.value.to_string(),
+            expires_at: entry.expires_at,
+        };
+
+        let path = self.path(key);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&record)?).await?;
+        fs::rename(&tmp, &path).await
+    }
+
+    /// every stored record, including expired ones
+    async fn records(&self) -> io::Result<Vec<(PathBuf, Record)>> {
+        let mut records = vec![];
+        let mut dir = fs::read_dir(&self.dir).await?;
+        while let Some(item) = dir.next().await {
+            let path: PathBuf = item?.path().into();
+            if path.extension().map_or(false, |ext| ext == "json") {
+                if let Some(record) = FileBackend::read(&path).await? {
+                    records.push((path, record));
+                }
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+impl CacheBackend for FileBackend {
+    fn get<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move { Ok(self.load(key, now).await?.map(|e| e.value)) })
+    }
+
+    fn insert(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, Option<String>> {
+        Box::pin(async move {
+            let prev = self.load(&key, now).await?;
+            self.store(&key, &entry).await?;
+            Ok(prev.map(|e| e.value))
+        })
+    }
+
+    fn remove<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let prev = self.load(key, now).await?;
+            FileBackend::delete(&self.path(key)).await?;
+            Ok(prev.map(|e| e.value))
+        })
+    }
+
+    fn ttl<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, KeyTtl> {
+        Box::pin(async move {
+            let ttl = match self.load(key, now).await? {
+                Some(Entry {
+                    expires_at: Some(t),
+                    ..
+                }) => KeyTtl::Expires(Duration::from_millis(t - now)),
+                Some(_) => KeyTtl::Persistent,
+                None => KeyTtl::Missing,
+            };
+            Ok(ttl)
+        })
+    }
+
+    fn persist<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, bool> {
+        Box::pin(async move {
+            match self.load(key, now).await? {
+                Some(mut entry) if entry.expires_at.is_some() => {
+                    entry.expires_at = None;
+                    self.store(key, &entry).await?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        })
+    }
+
+    fn take<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<Entry>> {
+        Box::pin(async move {
+            let entry = self.load(key, now).await?;
+            FileBackend::delete(&self.path(key)).await?;
+            Ok(entry)
+        })
+    }
+
+    fn put(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, bool> {
+        Box::pin(async move {
+            if entry.is_expired(now) || self.load(&key, now).await?.is_some() {
+                return Ok(false);
+            }
+            self.store(&key, &entry).await?;
+            Ok(true)
+        })
+    }
+
+    fn keys(&mut self, now: u64) -> BackendFuture<'_, Vec<String>> {
+        Box::pin(async move {
+            let entries = self.entries(now).await?;
+            Ok(entries.into_iter().map(|(key, _)| key).collect())
+        })
+    }
+
+    fn entries(&mut self, now: u64) -> BackendFuture<'_, Vec<(String, Entry)>> {
+        Box::pin(async move {
+            let entries = self
+                .records()
+                .await?
+                .into_iter()
+                .map(|(_, r)| (r.key, Entry::new(r.value, r.expires_at)))
+                .filter(|(_, entry)| !entry.is_expired(now))
+                .collect();
+            Ok(entries)
+        })
+    }
+
+    /// includes expired entries that have not yet been swept
+    fn len(&mut self, _now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(async move { Ok(self.records().await?.len()) })
+    }
+
+    fn sweep(&mut self, now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(async move {
+            let mut count = 0;
+            for (path, record) in self.records().await? {
+                if record.expires_at.map_or(false, |t| t <= now) {
+                    FileBackend::delete(&path).await?;
+                    count += 1;
+                }
+            }
+            Ok(count)
+        })
+    }
+
+    fn evictions(&self) -> u64 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend() {
+        async_std::task::block_on(async move {
+            let dir = std::env::temp_dir().join(format!("worker-lib-file-{}", fastrand::u64(..)));
+            let config = FileConfig::new(&dir);
+            let mut store = FileBackend::open(&config, 2).await.unwrap();
+
+            let entry = Entry::new("v1".to_string(), Some(1_500));
+            assert_eq!(
+                store.insert("a/b".to_string(), entry, 1_000).await.unwrap(),
+                None
+            );
+            let long = "k".repeat(500);
+            let entry = Entry::new("v2".to_string(), None);
+            assert!(store
+                .put(long.to_string(), entry.clone(), 1_000)
+                .await
+                .unwrap());
+            assert!(!store.put(long.to_string(), entry, 1_000).await.unwrap());
+
+            assert_eq!(
+                store.get("a/b", 1_000).await.unwrap(),
+                Some("v1".to_string())
+            );
+            assert_eq!(
+                store.get(&long, 1_000).await.unwrap(),
+                Some("v2".to_string())
+            );
+            let ttl = store.ttl("a/b", 1_000).await.unwrap();
+            assert_eq!(ttl, KeyTtl::Expires(Duration::from_millis(500)));
+            assert_eq!(store.len(1_000).await.unwrap(), 2);
+
+            // expired entries are hidden, then swept
+            assert_eq!(store.keys(2_000).await.unwrap(), vec![long.to_string()]);
+            assert_eq!(store.sweep(2_000).await.unwrap(), 1);
+            assert_eq!(store.len(2_000).await.unwrap(), 1);
+
+            // entries survive a reopen
+            let mut store = FileBackend::open(&config, 2).await.unwrap();
+            assert!(!store.persist(&long, 2_000).await.unwrap());
+            let taken = store.take(&long, 2_000).await.unwrap();
+            assert_eq!(taken, Some(Entry::new("v2".to_string(), None)));
+            assert!(store.is_empty(2_000).await.unwrap());
+            assert_eq!(store.remove(&long, 2_000).await.unwrap(), None);
+
+            let _ = fs::remove_dir_all(&dir).await;
+        });
+    }
+}
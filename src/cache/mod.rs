@@ -10,5 +10,21 @@
 /// to CPUs: level 1 is closest to the app, and the fastestest.  Level 2 is two
 /// steps away, e.g., hosted Redis and Level 3 is a SQL or Mongo hosted database.
 ///
+pub mod aof;
+pub mod backend;
+pub mod clock;
+pub mod events;
+pub mod eviction;
+pub mod file;
+pub mod pattern;
+pub mod redis;
+pub mod resp;
+pub mod scan;
+pub mod snapshot;
+#[cfg(test)]
+mod stand_in;
+pub mod store;
 pub mod supervisor;
+pub mod tiered;
+pub mod typed;
 pub mod worker;
@@ -0,0 +1,253 @@
+/// Redis style glob patterns for filtering keys
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    Any,
+    One,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    /// true if the token matches the single character
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            Token::Literal(l) => *l == c,
+            Token::One => true,
+            Token::Any => false,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi) != *negated
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPattern {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+impl KeyPattern {
+    /// parse the glob; an unclosed `[` is taken literally
+    pub fn new(pattern: &str) -> KeyPattern {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = vec![];
+        let mut n = 0;
+
+        while n < chars.len() {
+            let token = match chars[n] {
+                '*' => Token::Any,
+                '?' => Token::One,
+                '\\' if n + 1 < chars.len() => {
+                    n += 1;
+                    Token::Literal(chars[n])
+                }
+                '[' => match parse_class(&chars[n + 1..]) {
+                    Some((token, len)) => {
+                        n += len;
+                        token
+                    }
+                    None => Token::Literal('['),
+                },
+                c => Token::Literal(c),
+            };
+
+            // a run of stars is the same as one
+            if !(token == Token::Any && tokens.last() == Some(&Token::Any)) {
+                tokens.push(token);
+            }
+            n += 1;
+        }
+
+        KeyPattern {
+            source: pattern.to_string(),
+            tokens,
+        }
+    }
+
+    /// match every key that starts with the prefix
+    pub fn prefix(prefix: &str) -> KeyPattern {
+        let mut tokens: Vec<Token> = prefix.chars().map(Token::Literal).collect();
+        tokens.push(Token::Any);
+
+        KeyPattern {
+            source: format!("{}*", escape(prefix)),
+            tokens,
+        }
+    }
+
+    /// match every key
+    pub fn all() -> KeyPattern {
+        KeyPattern::new("*")
+    }
+
+    /// the pattern as written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// the literal characters every matching key starts with
+    pub fn literal_prefix(&self) -> String {
+        self.tokens
+            .iter()
+            .map_while(|t| match t {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// true if the whole key matches the pattern
+    pub fn matches(&self, key: &str) -> bool {
+        let key: Vec<char> = key.chars().collect();
+        let (mut t, mut k) = (0, 0);
+        // the last star and the key position it is matched up to, for backtracking
+        let mut star: Option<(usize, usize)> = None;
+
+        while k < key.len() {
+            match self.tokens.get(t) {
+                Some(Token::Any) => {
+                    star = Some((t, k));
+                    t += 1;
+                }
+                Some(token) if token.accepts(key[k]) => {
+                    t += 1;
+                    k += 1;
+                }
+                _ => match star {
+                    // let the star take one more character
+                    Some((st, sk)) => {
+                        star = Some((st, sk + 1));
+                        t = st + 1;
+                        k = sk + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        self.tokens[t..].iter().all(|token| *token == Token::Any)
+    }
+}
+
+impl Default for KeyPattern {
+    fn default() -> Self {
+        KeyPattern::all()
+    }
+}
+
+impl From<&str> for KeyPattern {
+    fn from(pattern: &str) -> Self {
+        KeyPattern::new(pattern)
+    }
+}
+
+impl fmt::Display for KeyPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// parse the class after a `[`; return the token and the number of characters used, including
+/// the closing `]`
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    let mut n = usize::from(negated);
+    let mut ranges = vec![];
+
+    while n < chars.len() {
+        let lo = match chars[n] {
+            ']' if !ranges.is_empty() || n > usize::from(negated) => {
+                return Some((Token::Class { negated, ranges }, n + 1));
+            }
+            '\\' if n + 1 < chars.len() => {
+                n += 1;
+                chars[n]
+            }
+            c => c,
+        };
+
+        if n + 2 < chars.len() && chars[n + 1] == '-' && chars[n + 2] != ']' {
+            let hi = chars[n + 2];
+            ranges.push((lo.min(hi), lo.max(hi)));
+            n += 3;
+        } else {
+            ranges.push((lo, lo));
+            n += 1;
+        }
+    }
+
+    None
+}
+
+/// escape the glob characters so the text matches literally
+pub fn escape(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        let cases = [
+            ("*", "", true),
+            ("*", "anything", true),
+            ("user:*", "user:42", true),
+            ("user:*", "users:42", false),
+            ("*:session", "user:1:session", true),
+            ("a*b*c", "a-x-b-y-c", true),
+            ("a*b*c", "a-x-b-y-d", false),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[!e]llo", "hallo", true),
+            ("h[!e]llo", "hello", false),
+            ("key-[0-9]", "key-7", true),
+            ("key-[0-9]", "key-x", false),
+            ("a\\*b", "a*b", true),
+            ("a\\*b", "axb", false),
+            ("open[", "open[", true),
+            ("exact", "exact", true),
+            ("exact", "exact!", false),
+            ("**a", "ba", true),
+        ];
+
+        for (pattern, key, expect) in cases {
+            assert_eq!(
+                KeyPattern::new(pattern).matches(key),
+                expect,
+                "{} {}",
+                pattern,
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn prefixes() {
+        let pattern = KeyPattern::prefix("a*[");
+        assert!(pattern.matches("a*[b"));
+        assert!(!pattern.matches("ab"));
+        assert_eq!(pattern.as_str(), "a\\*\\[*");
+        assert_eq!(KeyPattern::new(pattern.as_str()), pattern);
+        assert_eq!(pattern.literal_prefix(), "a*[");
+        assert_eq!(KeyPattern::new("user:?:x").literal_prefix(), "user:");
+        assert_eq!(KeyPattern::default().literal_prefix(), "");
+    }
+}
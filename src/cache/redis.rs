@@ -0,0 +1,309 @@
+/// the Redis cache backend; keys are stored as `<prefix>:<route>:<key>`.  Needs Redis 6.2 or later
+use crate::cache::backend::{BackendFuture, CacheBackend};
+use crate::cache::pattern::escape;
+use crate::cache::resp::{self, unexpected, Resp};
+use crate::cache::store::Entry;
+use crate::cache::worker::KeyTtl;
+use async_std::io::{BufReader, WriteExt};
+use async_std::net::TcpStream;
+use log::*;
+use std::io;
+use std::time::Duration;
+
+/// Redis backend options; the server must be Redis 6.2 or later
+#[derive(Debug, Clone)]
+pub struct RedisConfig {
+    /// the server's `host:port`
+    pub addr: String,
+    /// the namespace for the pool's keys
+    pub prefix: String,
+    /// the SCAN page size used to list a worker's keys
+    pub scan_count: usize,
+}
+
+impl RedisConfig {
+    pub fn new(addr: impl Into<String>) -> RedisConfig {
+        RedisConfig {
+            addr: addr.into(),
+            prefix: "worker-lib".to_string(),
+            scan_count: 1_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+#[derive(Debug)]
+pub struct RedisBackend {
+    addr: String,
+    namespace: String,
+    scan_count: usize,
+    conn: Option<Connection>,
+}
+
+impl RedisBackend {
+    /// connect and check the server answers
+    pub async fn connect(config: &RedisConfig, route: usize) -> io::Result<RedisBackend> {
+        let mut backend = RedisBackend {
+            addr: config.addr.to_string(),
+            namespace: format!("{}:{}:", config.prefix, route),
+            scan_count: config.scan_count.max(1),
+            conn: None,
+        };
+
+        backend.call(&["PING"]).await?;
+        info!("redis backend {} at {}", backend.namespace, backend.addr);
+
+        Ok(backend)
+    }
+
+    /// send the command and return the reply; an error reply is returned as an error
+    pub async fn call(&mut self, args: &[&str]) -> io::Result<Resp> {
+        if self.conn.is_none() {
+            let stream = TcpStream::connect(&self.addr).await?;
+            self.conn = Some(Connection {
+                reader: BufReader::new(stream.clone()),
+                writer: stream,
+            });
+        }
+
+        let conn = self.conn.as_mut().expect("connected");
+        let mut buf = vec![];
+        Resp::command(args).encode(&mut buf);
+
+        let reply = match conn.writer.write_all(&buf).await {
+            Ok(()) => resp::read(&mut conn.reader).await,
+            Err(e) => Err(e),
+        };
+
+        match reply {
+            Ok(Resp::Error(msg)) => Err(io::Error::new(io::ErrorKind::Other, msg)),
+            Ok(reply) => Ok(reply),
+            Err(e) => {
+                warn!("redis connection to {} dropped: {}", self.addr, e);
+                self.conn = None;
+                Err(e)
+            }
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.namespace, key)
+    }
+
+    /// the worker's keys without the namespace
+    async fn scan_keys(&mut self) -> io::Result<Vec<String>> {
+        let pattern = format!("{}*", escape(&self.namespace));
+        let count = self.scan_count.to_string();
+        let mut cursor = "0".to_string();
+        let mut keys = vec![];
+
+        loop {
+            let reply = self
+                .call(&["SCAN", &cursor, "MATCH", &pattern, "COUNT", &count])
+                .await?;
+            let mut page = reply.into_array()?.into_iter();
+            let (next, batch) = match (page.next(), page.next()) {
+                (Some(next), Some(batch)) => (next, batch),
+                _ => return Err(unexpected(&Resp::Array(None))),
+            };
+
+            for key in batch.into_array()? {
+                // a key outside the namespace is not the worker's
+                let key = key.into_string()?;
+                if let Some(key) = key.as_deref().and_then(|k| k.strip_prefix(&self.namespace)) {
+                    keys.push(key.to_string());
+                }
+            }
+
+            cursor = next.into_string()?.unwrap_or_default();
+            if cursor == "0" {
+                return Ok(keys);
+            }
+        }
+    }
+
+    /// the key's remaining time to live in milliseconds as an expiry time
+    async fn expires_at(&mut self, key: &str, now: u64) -> io::Result<Option<u64>> {
+        let ttl = self.call(&["PTTL", key]).await?.into_integer()?;
+        Ok(u64::try_from(ttl).ok().map(|ttl| now + ttl))
+    }
+
+    /// SET with the entry's expiry and the extra options
+    async fn set(
+        &mut self,
+        key: &str,
+        entry: &Entry,
+        now: u64,
+        options: &[&str],
+    ) -> io::Result<Resp> {
+        let key = self.key(key);
+        let mut args = vec!["SET", &key, &entry.value];
+        let ttl = entry.expires_at.map(|t| t.saturating_sub(now).to_string());
+        if let Some(ttl) = ttl.as_ref() {
+            args.extend(["PX", ttl.as_str()]);
+        }
+        args.extend(options.iter().copied());
+
+        self.call(&args).await
+    }
+}
+
+impl CacheBackend for RedisBackend {
+    fn get<'a>(&'a mut self, key: &'a str, _now: u64) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let key = self.key(key);
+            self.call(&["GET", &key]).await?.into_string()
+        })
+    }
+
+    fn insert(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, Option<String>> {
+        Box::pin(async move {
+            if entry.is_expired(now) {
+                return self.remove(&key, now).await;
+            }
+            self.set(&key, &entry, now, &["GET"]).await?.into_string()
+        })
+    }
+
+    fn remove<'a>(&'a mut self, key: &'a str, _now: u64) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let key = self.key(key);
+            self.call(&["GETDEL", &key]).await?.into_string()
+        })
+    }
+
+    fn ttl<'a>(&'a mut self, key: &'a str, _now: u64) -> BackendFuture<'a, KeyTtl> {
+        Box::pin(async move {
+            let key = self.key(key);
+            let ttl = match self.call(&["PTTL", &key]).await?.into_integer()? {
+                -1 => KeyTtl::Persistent,
+                ttl if ttl >= 0 => KeyTtl::Expires(Duration::from_millis(ttl as u64)),
+                _ => KeyTtl::Missing,
+            };
+            Ok(ttl)
+        })
+    }
+
+    fn persist<'a>(&'a mut self, key: &'a str, _now: u64) -> BackendFuture<'a, bool> {
+        Box::pin(async move {
+            let key = self.key(key);
+            Ok(self.call(&["PERSIST", &key]).await?.into_integer()? == 1)
+        })
+    }
+
+    fn take<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<Entry>> {
+        Box::pin(async move {
+            let key = self.key(key);
+            let value = match self.call(&["GET", &key]).await?.into_string()? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            let expires_at = self.expires_at(&key, now).await?;
+            self.call(&["DEL", &key]).await?;
+
+            Ok(Some(Entry::new(value, expires_at)))
+        })
+    }
+
+    fn put(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, bool> {
+        Box::pin(async move {
+            if entry.is_expired(now) {
+                return Ok(false);
+            }
+            Ok(self.set(&key, &entry, now, &["NX"]).await? == Resp::ok())
+        })
+    }
+
+    fn keys(&mut self, _now: u64) -> BackendFuture<'_, Vec<String>> {
+        Box::pin(self.scan_keys())
+    }
+
+    fn entries(&mut self, now: u64) -> BackendFuture<'_, Vec<(String, Entry)>> {
+        Box::pin(async move {
+            let mut entries = vec![];
+            for key in self.scan_keys().await? {
+                let full = self.key(&key);
+                if let Some(value) = self.call(&["GET", &full]).await?.into_string()? {
+                    let expires_at = self.expires_at(&full, now).await?;
+                    entries.push((key, Entry::new(value, expires_at)));
+                }
+            }
+
+            Ok(entries)
+        })
+    }
+
+    fn len(&mut self, _now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(async move { Ok(self.scan_keys().await?.len()) })
+    }
+
+    /// Redis expires keys itself
+    fn sweep(&mut self, _now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(futures::future::ready(Ok(0)))
+    }
+
+    /// eviction is left to the server's maxmemory policy
+    fn evictions(&self) -> u64 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::clock::{Clock, SystemClock};
+    use crate::cache::stand_in::StandInServer;
+
+    #[test]
+    fn backend() {
+        async_std::task::block_on(async move {
+            let server = StandInServer::start().await.unwrap();
+            let config = RedisConfig::new(server.addr());
+            let mut one = RedisBackend::connect(&config, 1).await.unwrap();
+            let mut two = RedisBackend::connect(&config, 2).await.unwrap();
+            let now = SystemClock.now_millis();
+
+            let entry = Entry::new("v1".to_string(), None);
+            assert_eq!(one.insert("a".to_string(), entry, now).await.unwrap(), None);
+            let entry = Entry::new("v2".to_string(), Some(now + 60_000));
+            let prev = one.insert("a".to_string(), entry, now).await.unwrap();
+            assert_eq!(prev, Some("v1".to_string()));
+            assert_eq!(one.get("a", now).await.unwrap(), Some("v2".to_string()));
+            assert!(matches!(
+                one.ttl("a", now).await.unwrap(),
+                KeyTtl::Expires(_)
+            ));
+
+            // the routes do not share keys
+            assert_eq!(two.get("a", now).await.unwrap(), None);
+            assert_eq!(one.keys(now).await.unwrap(), vec!["a".to_string()]);
+            assert_eq!(two.len(now).await.unwrap(), 0);
+
+            // hand the entry over with its expiry
+            let taken = one.take("a", now).await.unwrap().unwrap();
+            assert_eq!(taken.value, "v2");
+            assert!(taken.expires_at.map_or(false, |t| t > now));
+            assert!(two.put("a".to_string(), taken.clone(), now).await.unwrap());
+            assert!(!two.put("a".to_string(), taken, now).await.unwrap());
+            assert_eq!(one.len(now).await.unwrap(), 0);
+            assert_eq!(two.entries(now).await.unwrap().len(), 1);
+
+            assert!(two.persist("a", now).await.unwrap());
+            assert_eq!(two.ttl("a", now).await.unwrap(), KeyTtl::Persistent);
+            assert_eq!(two.remove("a", now).await.unwrap(), Some("v2".to_string()));
+            assert_eq!(two.ttl("a", now).await.unwrap(), KeyTtl::Missing);
+            assert!(server.is_empty());
+
+            // glob characters in the prefix are escaped
+            assert_eq!(escape("a*b"), "a\\*b");
+
+            let closed = RedisConfig::new("127.0.0.1:1");
+            assert!(RedisBackend::connect(&closed, 0).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,206 @@
+/// RESP, the Redis serialization protocol
+use async_std::io::prelude::BufReadExt;
+use async_std::io::{BufRead, Read, ReadExt};
+use futures::future::BoxFuture;
+use std::io;
+
+/// the largest bulk string accepted, the same as Redis
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// a RESP value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resp {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Resp>>),
+}
+
+impl Resp {
+    pub fn ok() -> Resp {
+        Resp::Simple("OK".to_string())
+    }
+
+    pub fn nil() -> Resp {
+        Resp::Bulk(None)
+    }
+
+    pub fn bulk(value: impl AsRef<[u8]>) -> Resp {
+        Resp::Bulk(Some(value.as_ref().to_vec()))
+    }
+
+    /// a command is sent as an array of bulk strings
+    pub fn command(args: &[&str]) -> Resp {
+        Resp::Array(Some(args.iter().map(Resp::bulk).collect()))
+    }
+
+    /// append the wire format to the buffer
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Resp::Simple(s) => buf.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            Resp::Error(s) => buf.extend_from_slice(format!("-{}\r\n", s).as_bytes()),
+            Resp::Integer(n) => buf.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            Resp::Bulk(None) => buf.extend_from_slice(b"$-1\r\n"),
+            Resp::Bulk(Some(b)) => {
+                buf.extend_from_slice(format!("${}\r\n", b.len()).as_bytes());
+                buf.extend_from_slice(b);
+                buf.extend_from_slice(b"\r\n");
+            }
+            Resp::Array(None) => buf.extend_from_slice(b"*-1\r\n"),
+            Resp::Array(Some(items)) => {
+                buf.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode(buf);
+                }
+            }
+        }
+    }
+
+    /// the value of a bulk or simple string; nil is None and an error reply is an error
+    pub fn into_string(self) -> io::Result<Option<String>> {
+        match self {
+            Resp::Bulk(None) => Ok(None),
+            Resp::Bulk(Some(b)) => String::from_utf8(b)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Resp::Simple(s) => Ok(Some(s)),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    pub fn into_integer(self) -> io::Result<i64> {
+        match self {
+            Resp::Integer(n) => Ok(n),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    pub fn into_array(self) -> io::Result<Vec<Resp>> {
+        match self {
+            Resp::Array(Some(items)) => Ok(items),
+            Resp::Array(None) => Ok(vec![]),
+            other => Err(unexpected(&other)),
+        }
+    }
+}
+
+/// the error for a reply of the wrong type
+pub fn unexpected(reply: &Resp) -> io::Error {
+    match reply {
+        Resp::Error(msg) => io::Error::new(io::ErrorKind::Other, msg.to_string()),
+        other => io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected reply: {:?}", other),
+        ),
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// read one value; a closed connection is `UnexpectedEof`
+pub fn read<R>(reader: &mut R) -> BoxFuture<'_, io::Result<Resp>>
+where
+    R: BufRead + Read + Unpin + Send,
+{
+    Box::pin(async move {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
+            ));
+        }
+
+        let line = line
+            .strip_suffix("\r\n")
+            .ok_or_else(|| invalid(format!("line not terminated: {:?}", line)))?;
+        if line.is_empty() {
+            return Err(invalid("empty line".to_string()));
+        }
+
+        let (kind, rest) = line.split_at(1);
+        let number = || {
+            rest.parse::<i64>()
+                .map_err(|_| invalid(format!("bad number: {:?}", rest)))
+        };
+
+        match kind {
+            "+" => Ok(Resp::Simple(rest.to_string())),
+            "-" => Ok(Resp::Error(rest.to_string())),
+            ":" => Ok(Resp::Integer(number()?)),
+            "$" => {
+                let len = number()?;
+                if len < 0 {
+                    return Ok(Resp::Bulk(None));
+                }
+                let len = len as usize;
+                if len > MAX_BULK_LEN {
+                    return Err(invalid(format!("bulk string too long: {}", len)));
+                }
+
+                let mut buf = vec![0; len + 2];
+                reader.read_exact(&mut buf).await?;
+                if !buf.ends_with(b"\r\n") {
+                    return Err(invalid("bulk string not terminated".to_string()));
+                }
+                buf.truncate(len);
+                Ok(Resp::Bulk(Some(buf)))
+            }
+            "*" => {
+                let len = number()?;
+                if len < 0 {
+                    return Ok(Resp::Array(None));
+                }
+
+                let mut items = Vec::with_capacity((len as usize).min(1024));
+                for _ in 0..len {
+                    items.push(read(&mut *reader).await?);
+                }
+                Ok(Resp::Array(Some(items)))
+            }
+            _ => Err(invalid(format!("unknown type: {:?}", kind))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::io::BufReader;
+
+    #[test]
+    fn codec() {
+        async_std::task::block_on(async move {
+            let values = [
+                Resp::ok(),
+                Resp::Error("ERR bad".to_string()),
+                Resp::Integer(-42),
+                Resp::nil(),
+                Resp::bulk("line\r\nbreak"),
+                Resp::Array(None),
+                Resp::Array(Some(vec![Resp::Integer(1), Resp::command(&["GET", "k"])])),
+            ];
+
+            let mut buf = vec![];
+            for value in values.iter() {
+                value.encode(&mut buf);
+            }
+
+            let mut reader = BufReader::new(&buf[..]);
+            for value in values.iter() {
+                assert_eq!(&read(&mut reader).await.unwrap(), value);
+            }
+
+            let err = read(&mut reader).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+            let mut reader = BufReader::new(&b"?what\r\n"[..]);
+            assert!(read(&mut reader).await.is_err());
+            let mut reader = BufReader::new(&b"$3\r\nabcd\r\n"[..]);
+            assert!(read(&mut reader).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,198 @@
+/// paged key scans across the pool's workers
+use crate::cache::pattern::KeyPattern;
+use crate::cache::supervisor::Supervisor;
+use crate::cache::worker::Command;
+use crate::supervisor::SupervisorError;
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+/// where a scan resumes; the default starts at the first worker
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCursor {
+    /// the worker to resume at
+    pub route: usize,
+    /// the last key returned from the worker; None to start at the worker's first key
+    pub after: Option<String>,
+}
+
+impl ScanCursor {
+    /// the cursor that starts a new scan
+    pub fn start() -> ScanCursor {
+        ScanCursor::default()
+    }
+}
+
+/// one page of a scan
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanPage {
+    pub keys: Vec<String>,
+    /// the cursor for the next page; None when the scan is complete
+    pub cursor: Option<ScanCursor>,
+}
+
+/// select the first `count` matching keys after the cursor key, in sorted order
+pub fn page<'k>(
+    keys: impl IntoIterator<Item = &'k str>,
+    pattern: &KeyPattern,
+    after: Option<&str>,
+    count: usize,
+) -> Vec<String> {
+    let mut keys: Vec<&str> = keys
+        .into_iter()
+        .filter(|key| after.map_or(true, |after| *key > after) && pattern.matches(key))
+        .collect();
+
+    if keys.len() > count {
+        keys.select_nth_unstable(count);
+        keys.truncate(count);
+    }
+    keys.sort_unstable();
+
+    keys.into_iter().map(|key| key.to_string()).collect()
+}
+
+impl Supervisor {
+    /// return up to `count` keys that match the glob pattern, starting at the cursor, e.g.,
+    /// `supervisor.scan("session:*", ScanCursor::start(), 100)`.  A page may span workers; it
+    /// has fewer than `count` keys only at the end of the scan.  Use `pattern::escape` to match a
+    /// prefix that contains glob characters.  A key present for the whole scan is returned
+    /// exactly once; a resize during the scan may miss or repeat keys.
+    pub async fn scan(
+        &self,
+        pattern: &str,
+        cursor: ScanCursor,
+        count: usize,
+    ) -> Result<ScanPage, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let pattern = KeyPattern::new(pattern);
+        let size = self.pool_size();
+        let count = count.max(1);
+
+        let mut keys: Vec<String> = vec![];
+        let mut cursor = Some(cursor).filter(|c| c.route < size);
+        while let Some(ScanCursor { route, after }) = cursor.take() {
+            let want = count - keys.len();
+            let page = self
+                .request(route, |tx| Command::Scan(pattern.clone(), after, want, tx))
+                .await?;
+
+            // a full page may have more keys after it; a short page finishes the worker
+            cursor = if page.len() == want {
+                page.last().map(|last| ScanCursor {
+                    route,
+                    after: Some(last.to_string()),
+                })
+            } else if route + 1 < size {
+                Some(ScanCursor {
+                    route: route + 1,
+                    after: None,
+                })
+            } else {
+                None
+            };
+
+            keys.extend(page);
+            if keys.len() >= count {
+                break;
+            }
+        }
+
+        Ok(ScanPage { keys, cursor })
+    }
+
+    /// return every key that matches the glob pattern as a stream, fetched `count` keys at a
+    /// time.  The stream ends after the last key or the first error.
+    pub fn scan_stream(
+        &self,
+        pattern: &str,
+        count: usize,
+    ) -> impl Stream<Item = Result<String, SupervisorError>> + Send + 'static {
+        let supervisor = self.with_timeout(self.timeout());
+        let pattern = pattern.to_string();
+
+        let pages = stream::unfold(Some(ScanCursor::start()), move |cursor| {
+            let supervisor = supervisor.with_timeout(supervisor.timeout());
+            let pattern = pattern.to_string();
+            async move {
+                match supervisor.scan(&pattern, cursor?, count).await {
+                    Ok(page) => Some((Ok(page.keys), page.cursor)),
+                    Err(e) => Some((Err(e), None)),
+                }
+            }
+        });
+
+        pages.flat_map(|page| {
+            let keys = match page {
+                Ok(keys) => keys.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            stream::iter(keys)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn pages() {
+        let keys = ["d", "a", "c", "b", "x:1", "e"];
+        let all = KeyPattern::all();
+
+        assert_eq!(page(keys, &all, None, 3), vec!["a", "b", "c"]);
+        assert_eq!(page(keys, &all, Some("c"), 2), vec!["d", "e"]);
+        assert_eq!(page(keys, &all, Some("e"), 10), vec!["x:1"]);
+        assert_eq!(page(keys, &KeyPattern::new("x:*"), None, 10), vec!["x:1"]);
+        assert!(page(keys, &all, Some("x:1"), 10).is_empty());
+    }
+
+    #[test]
+    fn scan() {
+        async_std::task::block_on(async move {
+            let supervisor = Supervisor::new(3).await.unwrap();
+            let items: Vec<(String, String)> = (0..50)
+                .map(|n| (format!("user:{:02}", n), "v".to_string()))
+                .chain((0..10).map(|n| (format!("job:{}", n), "v".to_string())))
+                .collect();
+            assert!(supervisor.mset(items).await.is_ok());
+
+            // page through the users; removing a key already returned moves nothing
+            let mut found = BTreeSet::new();
+            let mut cursor = Some(ScanCursor::start());
+            let mut pages = 0;
+            while let Some(next) = cursor {
+                let page = supervisor.scan("user:*", next, 7).await.unwrap();
+                assert!(page.keys.len() == 7 || page.cursor.is_none());
+                for key in page.keys.iter() {
+                    assert!(found.insert(key.to_string()), "{} returned twice", key);
+                }
+                if let Some(key) = page.keys.first() {
+                    assert!(supervisor.remove(key.to_string()).await.is_ok());
+                }
+                cursor = page.cursor;
+                pages += 1;
+            }
+            assert_eq!(found.len(), 50);
+            assert_eq!(pages, 8);
+
+            // a finished cursor past the last worker returns nothing
+            let past = ScanCursor {
+                route: 3,
+                after: None,
+            };
+            let page = supervisor.scan("*", past, 10).await.unwrap();
+            assert_eq!(page, ScanPage::default());
+
+            let keys: Vec<String> = supervisor
+                .scan_stream("job:?", 3)
+                .map(|key| key.unwrap())
+                .collect()
+                .await;
+            assert_eq!(keys.len(), 10);
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,388 @@
+/// json lines snapshots of the cache on disk
+use crate::cache::clock::{Clock, SystemClock};
+use crate::cache::store::Entry;
+use crate::cache::supervisor::Supervisor;
+use crate::cache::worker::{CacheConfig, Command};
+use crate::supervisor::{SupervisorConfig, SupervisorError, SupervisorState};
+use async_std::task::{self, JoinHandle};
+use futures::future::join_all;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// the snapshot file format version written by this release
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    created_at: u64,
+    count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    key: String,
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// a line of the file could not be parsed
+    Format {
+        line: usize,
+        message: String,
+    },
+    /// the file was written with an unsupported format version
+    Version(u32),
+    /// a worker did not return or accept its entries
+    Request(SupervisorError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot io error: {}", e),
+            SnapshotError::Format { line, message } => {
+                write!(f, "snapshot format error at line {}: {}", line, message)
+            }
+            SnapshotError::Version(v) => write!(f, "unsupported snapshot version: {}", v),
+            SnapshotError::Request(e) => write!(f, "snapshot request error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(e) => Some(e),
+            SnapshotError::Request(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<SupervisorError> for SnapshotError {
+    fn from(e: SupervisorError) -> Self {
+        SnapshotError::Request(e)
+    }
+}
+
+impl Supervisor {
+    /// write the live entries from every worker to the file; return the number of entries
+    pub async fn snapshot(&self, path: impl AsRef<Path>) -> Result<usize, SnapshotError> {
+        let mut entries = vec![];
+        {
+            // entries in the middle of a resize move are in neither worker
+            let _routing = self.routing_guard().await;
+            for resp in self.request_all(Command::Dump).await {
+                entries.extend(resp?);
+            }
+        }
+
+        let count = entries.len();
+        let path = path.as_ref().to_path_buf();
+        info!("snapshot {} entries to {:?}", count, path);
+        task::spawn_blocking(move || write_snapshot(&path, entries)).await?;
+
+        Ok(count)
+    }
+
+    /// load the snapshot's entries into the pool, routed for the current pool size; return the
+    /// number stored.  Expired entries and keys that already have a value are skipped.
+    pub async fn restore(&self, path: impl AsRef<Path>) -> Result<usize, SnapshotError> {
+        let path = path.as_ref().to_path_buf();
+        let entries = task::spawn_blocking(move || read_snapshot(&path)).await?;
+
+        let _routing = self.routing_guard().await;
+        let mut batches: BTreeMap<usize, Vec<(String, Entry)>> = BTreeMap::new();
+        for (key, entry) in entries {
+            let route = self.get_route(&key)?;
+            batches.entry(route).or_default().push((key, entry));
+        }
+
+        let requests = batches
+            .into_iter()
+            .map(|(route, batch)| self.request(route, |tx| Command::Put(batch, tx)));
+
+        let mut count = 0;
+        for resp in join_all(requests).await {
+            count += resp?;
+        }
+
+        info!("restored {} entries", count);
+        Ok(count)
+    }
+
+    /// create and start the pool, then restore the snapshot if the file exists
+    pub async fn new_from_snapshot(
+        pool_size: usize,
+        config: SupervisorConfig<CacheConfig>,
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<Supervisor> {
+        let supervisor = Supervisor::with_config(pool_size, config).await?;
+        if path.as_ref().exists() {
+            supervisor.restore(path).await?;
+        } else {
+            info!("no snapshot at {:?}, starting empty", path.as_ref());
+        }
+
+        Ok(supervisor)
+    }
+
+    /// snapshot to the file every `interval` until the supervisor stops running.  Errors are
+    /// logged and the next snapshot is tried on schedule; cancel the task to stop early.
+    pub fn snapshot_every(&self, path: impl AsRef<Path>, interval: Duration) -> JoinHandle<()> {
+        let supervisor = self.with_timeout(self.timeout());
+        let path = path.as_ref().to_path_buf();
+
+        task::spawn(async move {
+            loop {
+                task::sleep(interval).await;
+                if supervisor.state() != SupervisorState::Running {
+                    break;
+                }
+
+                if let Err(e) = supervisor.snapshot(&path).await {
+                    error!("periodic snapshot failed: {}", e);
+                }
+            }
+        })
+    }
+}
+
+/// the temp file the snapshot is written to before the rename
+fn temp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+fn write_snapshot(path: &Path, entries: Vec<(String, Entry)>) -> Result<(), SnapshotError> {
+    let tmp = temp_path(path);
+    let file = File::create(&tmp)?;
+    let mut writer = BufWriter::new(file);
+
+    let header = Header {
+        version: SNAPSHOT_VERSION,
+        created_at: SystemClock.now_millis(),
+        count: entries.len(),
+    };
+    serde_json::to_writer(&mut writer, &header).map_err(io::Error::from)?;
+    writer.write_all(b"\n")?;
+
+    for (key, entry) in entries {
+        let record = Record {
+            key,
+            value: entry.value,
+            expires_at: entry.expires_at,
+        };
+        serde_json::to_writer(&mut writer, &record).map_err(io::Error::from)?;
+        writer.write_all(b"\n")?;
+    }
+
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+fn read_snapshot(path: &Path) -> Result<Vec<(String, Entry)>, SnapshotError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines().enumerate();
+    let format_error = |line: usize, message: String| SnapshotError::Format {
+        line: line + 1,
+        message,
+    };
+
+    let header: Header = match lines.next() {
+        Some((n, line)) => {
+            serde_json::from_str(&line?).map_err(|e| format_error(n, e.to_string()))?
+        }
+        None => return Err(format_error(0, "missing header".to_string())),
+    };
+
+    if header.version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::Version(header.version));
+    }
+
+    let mut entries = Vec::with_capacity(header.count);
+    for (n, line) in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: Record =
+            serde_json::from_str(&line).map_err(|e| format_error(n, e.to_string()))?;
+        entries.push((record.key, Entry::new(record.value, record.expires_at)));
+    }
+
+    if entries.len() != header.count {
+        let message = format!("expected {} entries, found {}", header.count, entries.len());
+        return Err(format_error(entries.len() + 1, message));
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::clock::ManualClock;
+    use crate::cache::worker::KeyTtl;
+    use std::sync::Arc;
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("worker-lib-{}", fastrand::u64(..)));
+        fs::create_dir_all(&dir).expect("should create the temp dir");
+        dir.join(name)
+    }
+
+    fn config(clock: &ManualClock) -> SupervisorConfig<CacheConfig> {
+        SupervisorConfig {
+            worker: CacheConfig {
+                clock: Arc::new(clock.clone()),
+                ..CacheConfig::default()
+            },
+            ..SupervisorConfig::default()
+        }
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        async_std::task::block_on(async move {
+            let path = snapshot_path("cache.snapshot");
+            let clock = ManualClock::new(1_000_000);
+            let supervisor = Supervisor::with_config(3, config(&clock))
+                .await
+                .expect("should create the supervisor");
+
+            let items: Vec<(String, String)> = (0..100)
+                .map(|n| (format!("key-{}", n), format!("{{\"n\":{}}}", n)))
+                .collect();
+            assert!(supervisor.mset(items).await.is_ok());
+            let ttl = Duration::from_secs(60);
+            for (key, ttl) in [("short", ttl), ("long", ttl * 10)] {
+                let r = supervisor.set_with_ttl(key.to_string(), "x".to_string(), ttl);
+                assert!(r.await.is_ok());
+            }
+
+            assert_eq!(supervisor.snapshot(&path).await.unwrap(), 102);
+            assert!(path.exists());
+            assert!(!temp_path(&path).exists());
+            assert!(supervisor.shutdown().await.is_ok());
+
+            // restore into a larger pool after the short ttl has passed
+            clock.advance(ttl * 2);
+            let supervisor = Supervisor::new_from_snapshot(5, config(&clock), &path)
+                .await
+                .expect("should restore the snapshot");
+            assert_eq!(supervisor.len().await, Ok(101));
+            let r = supervisor.get("key-42".to_string()).await.unwrap();
+            assert_eq!(r, Some("{\"n\":42}".to_string()));
+            let r = supervisor.ttl("long".to_string()).await.unwrap();
+            assert_eq!(r, KeyTtl::Expires(ttl * 8));
+            let r = supervisor.ttl("short".to_string()).await.unwrap();
+            assert_eq!(r, KeyTtl::Missing);
+
+            // keys that already have a value keep it
+            let r = supervisor.set("key-1".to_string(), "new".to_string()).await;
+            assert!(r.is_ok());
+            assert_eq!(supervisor.restore(&path).await.unwrap(), 0);
+            let r = supervisor.get("key-1".to_string()).await.unwrap();
+            assert_eq!(r, Some("new".to_string()));
+
+            assert!(supervisor.shutdown().await.is_ok());
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+        });
+    }
+
+    #[test]
+    fn snapshot_errors() {
+        async_std::task::block_on(async move {
+            let path = snapshot_path("bad.snapshot");
+            let supervisor = Supervisor::new(2)
+                .await
+                .expect("should create the supervisor");
+
+            // a missing file is an error for restore but not at startup
+            let r = supervisor.restore(&path).await;
+            assert!(matches!(r, Err(SnapshotError::Io(_))));
+            let empty = Supervisor::new_from_snapshot(1, SupervisorConfig::default(), &path)
+                .await
+                .expect("should start empty");
+            assert_eq!(empty.len().await, Ok(0));
+
+            fs::write(&path, "{\"version\":99,\"created_at\":0,\"count\":0}\n").unwrap();
+            let r = supervisor.restore(&path).await;
+            assert!(matches!(r, Err(SnapshotError::Version(99))));
+
+            let header = "{\"version\":1,\"created_at\":0,\"count\":2}";
+            let data = format!("{}\n{{\"key\":\"a\",\"value\":\"1\"}}\nnot json\n", header);
+            fs::write(&path, data).unwrap();
+            let r = supervisor.restore(&path).await;
+            assert!(matches!(r, Err(SnapshotError::Format { line: 3, .. })));
+
+            // a truncated file
+            fs::write(
+                &path,
+                format!("{}\n{{\"key\":\"a\",\"value\":\"1\"}}\n", header),
+            )
+            .unwrap();
+            let r = supervisor.restore(&path).await;
+            assert!(matches!(r, Err(SnapshotError::Format { .. })));
+            assert_eq!(supervisor.len().await, Ok(0));
+
+            assert!(empty.shutdown().await.is_ok());
+            assert!(supervisor.shutdown().await.is_ok());
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+        });
+    }
+
+    #[test]
+    fn periodic_snapshots() {
+        async_std::task::block_on(async move {
+            let path = snapshot_path("periodic.snapshot");
+            let supervisor = Supervisor::new(2)
+                .await
+                .expect("should create the supervisor");
+            let r = supervisor.set("key".to_string(), "1".to_string()).await;
+            assert!(r.is_ok());
+
+            let handle = supervisor.snapshot_every(&path, Duration::from_millis(20));
+            task::sleep(Duration::from_millis(100)).await;
+            assert!(path.exists());
+
+            // the task stops once the supervisor shuts down
+            assert!(supervisor.shutdown().await.is_ok());
+            handle.await;
+
+            let restored = Supervisor::new_from_snapshot(3, SupervisorConfig::default(), &path)
+                .await
+                .expect("should restore the snapshot");
+            assert_eq!(
+                restored.get("key".to_string()).await,
+                Ok(Some("1".to_string()))
+            );
+
+            assert!(restored.shutdown().await.is_ok());
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+        });
+    }
+}
@@ -0,0 +1,206 @@
+/// a stand-in Redis server for the Redis backend tests
+use crate::cache::clock::{Clock, SystemClock};
+use crate::cache::pattern::KeyPattern;
+use crate::cache::resp::{read, Resp};
+use crate::cache::store::{Entry, MemoryStore};
+use crate::cache::worker::KeyTtl;
+use async_std::io::{BufReader, WriteExt};
+use async_std::net::{SocketAddr, TcpListener, TcpStream};
+use async_std::task;
+use log::*;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// An in-process server that speaks enough RESP for the Redis backend: PING, GET, SET with PX,
+/// NX, XX and GET, GETDEL, DEL, PTTL, PERSIST, SCAN, DBSIZE and FLUSHALL.  SCAN returns every
+/// match in one page and only understands literal and trailing `*` patterns.  Entries are kept
+/// in a `MemoryStore` on the system clock.
+#[derive(Debug, Clone)]
+pub struct StandInServer {
+    addr: SocketAddr,
+    store: Arc<Mutex<MemoryStore>>,
+}
+
+impl StandInServer {
+    /// listen on a free local port
+    pub async fn start() -> io::Result<StandInServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let store = Arc::new(Mutex::new(MemoryStore::default()));
+
+        let shared = store.clone();
+        task::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let store = shared.clone();
+                        task::spawn(async move {
+                            if let Err(e) = serve(stream, store).await {
+                                warn!("stand-in connection error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("stand-in accept error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        info!("stand-in resp server listening on {}", addr);
+        Ok(StandInServer { addr, store })
+    }
+
+    /// the address to connect to
+    pub fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// the number of live keys on the server
+    pub fn len(&self) -> usize {
+        let store = self.store.lock().unwrap_or_else(|e| e.into_inner());
+        store.keys(SystemClock.now_millis()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+async fn serve(stream: TcpStream, store: Arc<Mutex<MemoryStore>>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.clone());
+    let mut writer = stream;
+
+    loop {
+        let request = match read(&mut reader).await {
+            Ok(request) => request,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let response = {
+            let mut store = store.lock().unwrap_or_else(|e| e.into_inner());
+            execute(request, &mut store, SystemClock.now_millis())
+        };
+
+        let mut buf = vec![];
+        response.encode(&mut buf);
+        writer.write_all(&buf).await?;
+    }
+}
+
+fn optional(value: Option<String>) -> Resp {
+    value.map(Resp::bulk).unwrap_or_else(Resp::nil)
+}
+
+fn execute(request: Resp, store: &mut MemoryStore, now: u64) -> Resp {
+    let args: Vec<String> = match request {
+        Resp::Array(Some(items)) => items
+            .into_iter()
+            .filter_map(|item| item.into_string().ok().flatten())
+            .collect(),
+        _ => return Resp::Error("ERR protocol error".to_string()),
+    };
+    let name = args.first().map(|a| a.to_uppercase()).unwrap_or_default();
+
+    match (name.as_str(), &args[1.min(args.len())..]) {
+        ("PING", []) => Resp::Simple("PONG".to_string()),
+        ("GET", [key]) => optional(store.get(key, now)),
+        ("GETDEL", [key]) => optional(store.remove(key, now)),
+        ("DEL", keys) if !keys.is_empty() => {
+            let count = keys.iter().filter_map(|k| store.remove(k, now)).count();
+            Resp::Integer(count as i64)
+        }
+        ("PTTL", [key]) => match store.ttl(key, now) {
+            KeyTtl::Missing => Resp::Integer(-2),
+            KeyTtl::Persistent => Resp::Integer(-1),
+            KeyTtl::Expires(ttl) => Resp::Integer(ttl.as_millis() as i64),
+        },
+        ("PERSIST", [key]) => Resp::Integer(i64::from(store.persist(key, now))),
+        ("SET", [key, value, options @ ..]) => set(store, key, value, options, now),
+        ("SCAN", [_cursor, options @ ..]) => {
+            let pattern = KeyPattern::new(option_value(options, "MATCH").unwrap_or("*"));
+            let keys = store
+                .keys(now)
+                .into_iter()
+                .filter(|key| pattern.matches(key))
+                .map(Resp::bulk)
+                .collect();
+            Resp::Array(Some(vec![Resp::bulk("0"), Resp::Array(Some(keys))]))
+        }
+        ("DBSIZE", []) => Resp::Integer(store.keys(now).len() as i64),
+        ("FLUSHALL", []) => {
+            *store = MemoryStore::default();
+            Resp::ok()
+        }
+        _ => Resp::Error(format!("ERR unknown command or arguments '{}'", name)),
+    }
+}
+
+fn option_value<'a>(options: &'a [String], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .position(|o| o.eq_ignore_ascii_case(name))
+        .and_then(|n| options.get(n + 1))
+        .map(|v| v.as_str())
+}
+
+fn set(store: &mut MemoryStore, key: &str, value: &str, options: &[String], now: u64) -> Resp {
+    let has = |name: &str| options.iter().any(|o| o.eq_ignore_ascii_case(name));
+    let expires_at = match option_value(options, "PX").map(|ms| ms.parse::<u64>()) {
+        Some(Ok(ms)) => Some(now + ms),
+        Some(Err(_)) => return Resp::Error("ERR value is not an integer".to_string()),
+        None => None,
+    };
+
+    let current = store.get(key, now);
+    let reply = |prev: Option<String>, stored: bool| match (has("GET"), stored) {
+        (true, _) => optional(prev),
+        (false, true) => Resp::ok(),
+        (false, false) => Resp::nil(),
+    };
+
+    if (has("NX") && current.is_some()) || (has("XX") && current.is_none()) {
+        return reply(current, false);
+    }
+
+    let prev = store.insert(
+        key.to_string(),
+        Entry::new(value.to_string(), expires_at),
+        now,
+    );
+    reply(prev, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stand_in() {
+        let mut store = MemoryStore::default();
+        let mut run = |args: &[&str]| execute(Resp::command(args), &mut store, 1_000);
+
+        assert_eq!(run(&["ping"]), Resp::Simple("PONG".to_string()));
+        assert_eq!(run(&["SET", "a", "1"]), Resp::ok());
+        assert_eq!(run(&["SET", "a", "2", "NX"]), Resp::nil());
+        assert_eq!(run(&["SET", "b", "2", "XX", "GET"]), Resp::nil());
+        assert_eq!(run(&["SET", "a", "3", "PX", "500", "GET"]), Resp::bulk("1"));
+        assert_eq!(run(&["PTTL", "a"]), Resp::Integer(500));
+        assert_eq!(run(&["PERSIST", "a"]), Resp::Integer(1));
+        assert_eq!(run(&["PTTL", "a"]), Resp::Integer(-1));
+        assert_eq!(run(&["PTTL", "b"]), Resp::Integer(-2));
+        assert_eq!(run(&["SET", "p:x", "x"]), Resp::ok());
+
+        let page = run(&["SCAN", "0", "MATCH", "p:*", "COUNT", "10"]);
+        let expect = vec![Resp::bulk("0"), Resp::Array(Some(vec![Resp::bulk("p:x")]))];
+        assert_eq!(page, Resp::Array(Some(expect)));
+        assert_eq!(run(&["DBSIZE"]), Resp::Integer(2));
+
+        assert_eq!(run(&["GETDEL", "a"]), Resp::bulk("3"));
+        assert_eq!(run(&["DEL", "a", "p:x"]), Resp::Integer(1));
+        assert!(matches!(run(&["NOPE"]), Resp::Error(_)));
+        assert!(matches!(run(&["SET", "a", "1", "PX", "x"]), Resp::Error(_)));
+    }
+}
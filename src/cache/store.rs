@@ -0,0 +1,400 @@
+/// the in-memory entry store; handles expiry and evicts entries when bounded
+use crate::cache::eviction::{EvictionPolicy, Evictor};
+use crate::cache::pattern::KeyPattern;
+use crate::cache::scan;
+use crate::cache::worker::KeyTtl;
+use hashbrown::HashMap;
+use log::*;
+use std::time::Duration;
+
+/// a cached value with an optional expiry time in unix epoch milliseconds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub value: String,
+    pub expires_at: Option<u64>,
+}
+
+impl Entry {
+    pub fn new(value: String, expires_at: Option<u64>) -> Entry {
+        Entry { value, expires_at }
+    }
+
+    /// return true if the entry has expired at the given time
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(t) if t <= now)
+    }
+}
+
+/// an entry the store removed on its own rather than by request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Removal {
+    Expired(String),
+    Evicted(String),
+}
+
+/// the number of bytes counted against the store's byte limit
+fn entry_size(key: &str, entry: &Entry) -> usize {
+    key.len() + entry.value.len()
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    map: HashMap<String, Entry>,
+    bytes: usize,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    evictor: Option<Evictor>,
+    evictions: u64,
+    /// expired and evicted keys since the last `drain_removals`
+    removals: Vec<Removal>,
+}
+
+impl MemoryStore {
+    /// create a store; the evictor only tracks keys when there is a limit to enforce
+    pub fn new(
+        max_entries: Option<usize>,
+        max_bytes: Option<usize>,
+        policy: EvictionPolicy,
+    ) -> MemoryStore {
+        let evictor = if max_entries.is_some() || max_bytes.is_some() {
+            Some(Evictor::new(policy))
+        } else {
+            None
+        };
+
+        MemoryStore {
+            max_entries,
+            max_bytes,
+            evictor,
+            ..MemoryStore::default()
+        }
+    }
+
+    /// return the value if the key exists and has not expired; expired entries are removed
+    pub fn get(&mut self, key: &str, now: u64) -> Option<String> {
+        let value = match self.map.get(key) {
+            Some(entry) if entry.is_expired(now) => None,
+            Some(entry) => Some(entry.value.to_string()),
+            None => return None,
+        };
+
+        if value.is_some() {
+            self.touch(key);
+        } else {
+            self.expire(key);
+        }
+
+        value
+    }
+
+    /// store the entry, evicting others if the store is full; return the previous live value
+    pub fn insert(&mut self, key: String, entry: Entry, now: u64) -> Option<String> {
+        let size = entry_size(&key, &entry);
+        let (is_new, old_size) = match self.map.get(&key) {
+            Some(old) => (false, entry_size(&key, old)),
+            None => (true, 0),
+        };
+
+        self.make_room(&key, is_new, old_size, size);
+
+        let prev = self.map.insert(key.to_string(), entry);
+        self.bytes = self.bytes - old_size + size;
+        self.touch(&key);
+
+        prev.filter(|e| !e.is_expired(now)).map(|e| e.value)
+    }
+
+    /// remove the entry; return the value if it was live
+    pub fn remove(&mut self, key: &str, now: u64) -> Option<String> {
+        self.take(key, now).map(|e| e.value)
+    }
+
+    /// return the time to live for the key
+    pub fn ttl(&self, key: &str, now: u64) -> KeyTtl {
+        match self.map.get(key) {
+            Some(entry) if entry.is_expired(now) => KeyTtl::Missing,
+            Some(Entry {
+                expires_at: Some(t),
+                ..
+            }) => KeyTtl::Expires(Duration::from_millis(t - now)),
+            Some(_) => KeyTtl::Persistent,
+            None => KeyTtl::Missing,
+        }
+    }
+
+    /// remove and return the live entry; used to hand the entry to another worker
+    pub fn take(&mut self, key: &str, now: u64) -> Option<Entry> {
+        let entry = self.remove_entry(key)?;
+        if entry.is_expired(now) {
+            self.removals.push(Removal::Expired(key.to_string()));
+            return None;
+        }
+
+        Some(entry)
+    }
+
+    /// store an entry handed over from another worker unless the key already has a live value;
+    /// return true if it was stored
+    pub fn put(&mut self, key: String, entry: Entry, now: u64) -> bool {
+        if entry.is_expired(now) || self.get(&key, now).is_some() {
+            return false;
+        }
+
+        self.insert(key, entry, now);
+        true
+    }
+
+    /// remove the key's expiry; return true if it had one
+    pub fn persist(&mut self, key: &str, now: u64) -> bool {
+        match self.map.get_mut(key) {
+            Some(entry) if !entry.is_expired(now) => entry.expires_at.take().is_some(),
+            _ => false,
+        }
+    }
+
+    /// return all live keys
+    pub fn keys(&self, now: u64) -> Vec<String> {
+        self.map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, _)| key.to_string())
+            .collect()
+    }
+
+    /// return up to `count` live keys that match the pattern and sort after the `after` key
+    pub fn scan(
+        &self,
+        pattern: &KeyPattern,
+        after: Option<&str>,
+        count: usize,
+        now: u64,
+    ) -> Vec<String> {
+        let live = self
+            .map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, _)| key.as_str());
+
+        scan::page(live, pattern, after, count)
+    }
+
+    /// return a copy of all live entries
+    pub fn entries(&self, now: u64) -> Vec<(String, Entry)> {
+        self.map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, entry)| (key.to_string(), entry.clone()))
+            .collect()
+    }
+
+    /// the number of stored entries; includes expired entries that have not yet been swept
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// the total size of all keys and values
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// the number of entries evicted to make room for new ones
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// remove all expired entries; return the number removed
+    pub fn sweep(&mut self, now: u64) -> usize {
+        let expired: Vec<String> = self
+            .map
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.to_string())
+            .collect();
+
+        for key in expired.iter() {
+            self.expire(key);
+        }
+
+        expired.len()
+    }
+
+    /// the keys expired or evicted since the last call
+    pub fn drain_removals(&mut self) -> Vec<Removal> {
+        std::mem::take(&mut self.removals)
+    }
+
+    fn expire(&mut self, key: &str) {
+        if self.remove_entry(key).is_some() {
+            self.removals.push(Removal::Expired(key.to_string()));
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(evictor) = self.evictor.as_mut() {
+            evictor.touch(key);
+        }
+    }
+
+    fn remove_entry(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.map.remove(key)?;
+        self.bytes -= entry_size(key, &entry);
+        if let Some(evictor) = self.evictor.as_mut() {
+            evictor.remove(key);
+        }
+
+        Some(entry)
+    }
+
+    /// evict entries until the write of `key` fits within the limits
+    fn make_room(&mut self, key: &str, is_new: bool, old_size: usize, size: usize) {
+        loop {
+            let entries = self.map.len() + usize::from(is_new);
+            let bytes = self.bytes - old_size + size;
+
+            let over = self.max_entries.map_or(false, |max| entries > max)
+                || self.max_bytes.map_or(false, |max| bytes > max);
+            if !over {
+                break;
+            }
+
+            let victim = match self.evictor.as_ref().and_then(|e| e.victim(key)) {
+                Some(victim) => victim,
+                None => {
+                    warn!("entry for key: {} exceeds the cache limits", key);
+                    break;
+                }
+            };
+
+            info!("evict key: {}", victim);
+            self.remove_entry(&victim);
+            self.evictions += 1;
+            self.removals.push(Removal::Evicted(victim));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(v: &str) -> Entry {
+        Entry::new(v.to_string(), None)
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut store = MemoryStore::default();
+        for n in 0..100 {
+            store.insert(format!("k{:02}", n), value("0123456789"), 0);
+        }
+
+        assert_eq!(store.len(), 100);
+        assert_eq!(store.bytes(), 100 * 13);
+        assert_eq!(store.evictions(), 0);
+
+        assert_eq!(store.remove("k00", 0), Some("0123456789".to_string()));
+        assert_eq!(store.bytes(), 99 * 13);
+    }
+
+    #[test]
+    fn max_entries() {
+        let mut store = MemoryStore::new(Some(3), None, EvictionPolicy::Lru);
+        for key in ["a", "b", "c"] {
+            store.insert(key.to_string(), value(key), 0);
+        }
+
+        // reading a makes b the least recently used
+        assert_eq!(store.get("a", 0), Some("a".to_string()));
+        assert_eq!(store.insert("d".to_string(), value("d"), 0), None);
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.evictions(), 1);
+        assert_eq!(
+            store.drain_removals(),
+            vec![Removal::Evicted("b".to_string())]
+        );
+        assert_eq!(store.get("b", 0), None);
+
+        // updating an existing key does not evict
+        assert_eq!(
+            store.insert("c".to_string(), value("cc"), 0),
+            Some("c".to_string())
+        );
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.evictions(), 1);
+    }
+
+    #[test]
+    fn max_bytes() {
+        let mut store = MemoryStore::new(None, Some(20), EvictionPolicy::Fifo);
+        store.insert("k1".to_string(), value("12345678"), 0);
+        store.insert("k2".to_string(), value("12345678"), 0);
+        assert_eq!(store.bytes(), 20);
+
+        // needs to evict the oldest
+        store.insert("k3".to_string(), value("1234"), 0);
+        assert_eq!(store.evictions(), 1);
+        assert_eq!(store.bytes(), 16);
+        assert_eq!(store.get("k1", 0), None);
+
+        // growing k2 pushes out k3 but never k2 itself
+        store.insert("k2".to_string(), value("1234567890123456"), 0);
+        assert_eq!(store.evictions(), 2);
+        assert_eq!(store.bytes(), 18);
+        assert_eq!(store.keys(0), vec!["k2".to_string()]);
+
+        // an entry larger than the limit is stored on its own
+        store.insert("big".to_string(), value("123456789012345678901234"), 0);
+        assert_eq!(store.keys(0), vec!["big".to_string()]);
+        assert_eq!(store.evictions(), 3);
+    }
+
+    #[test]
+    fn sweep_entries() {
+        let mut store = MemoryStore::new(Some(10), None, EvictionPolicy::Lfu);
+        store.insert("a".to_string(), Entry::new("1".to_string(), Some(100)), 0);
+        store.insert("b".to_string(), Entry::new("2".to_string(), Some(200)), 0);
+        store.insert("c".to_string(), Entry::new("3".to_string(), None), 0);
+
+        assert_eq!(store.sweep(99), 0);
+        assert_eq!(store.sweep(100), 1);
+        assert_eq!(store.sweep(1_000), 1);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.bytes(), 2);
+        assert_eq!(store.keys(1_000), vec!["c".to_string()]);
+        assert_eq!(store.evictions(), 0);
+        assert_eq!(
+            store.drain_removals(),
+            vec![
+                Removal::Expired("a".to_string()),
+                Removal::Expired("b".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn take_put() {
+        let mut store = MemoryStore::default();
+        store.insert("a".to_string(), value("1"), 0);
+        store.insert("b".to_string(), Entry::new("2".to_string(), Some(100)), 0);
+
+        assert_eq!(store.take("a", 0), Some(value("1")));
+        assert_eq!(store.take("a", 0), None);
+        assert_eq!(store.take("b", 100), None);
+        assert!(store.is_empty());
+
+        // a live value is newer than the handed over entry
+        store.insert("c".to_string(), value("new"), 0);
+        assert!(!store.put("c".to_string(), value("old"), 0));
+        assert!(store.put("d".to_string(), Entry::new("4".to_string(), Some(50)), 0));
+        assert!(!store.put("e".to_string(), Entry::new("5".to_string(), Some(50)), 50));
+        assert_eq!(store.get("c", 0), Some("new".to_string()));
+        assert_eq!(
+            store.ttl("d", 0),
+            KeyTtl::Expires(Duration::from_millis(50))
+        );
+    }
+}
@@ -1,228 +1,426 @@
 /// The worker supervisor is responsible for creating, monitoring and destroying workers in it's pool.
 /// It also serves as the primary API to the outside clients specific to it's domain.  For the cache
-/// worker pool the
+/// worker pool the generic supervisor is extended with the key/value API: set, get, remove, keys
+/// and len, the batch mget, mset and mremove, the atomic set_nx, set_xx, compare_and_swap,
+/// incr_by, decr_by and append, and keyspace change subscriptions.
 use crate::{
-    cache::worker::{Command, Worker},
-    worker::{JsonString, WorkerStatus},
+    cache::events::{CacheEvent, SubscribeOptions},
+    cache::pattern::KeyPattern,
+    cache::store::Entry,
+    cache::typed::{decode, encode, TypedError},
+    cache::worker::{Command, KeyTtl, Worker},
+    supervisor::{PartialError, SupervisorError},
+    worker::{JsonString, TierStats},
 };
-use anyhow::{anyhow, Result};
-use domain_keys::keys::RouteKey;
+use async_channel::{Receiver, Sender};
+use futures::future::join_all;
 use log::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
-// add generics to this based on the WorkerTrait
-#[derive(Debug, Default)]
-pub struct Supervisor {
-    pub pool_size: usize,
-    pub auto_routing: bool,
-    pub workers: Vec<Worker>,
-}
+/// the cache supervisor is the generic supervisor over a pool of cache workers
+pub type Supervisor = crate::supervisor::Supervisor<Worker>;
 
 impl Supervisor {
-    /// create and start the worker pool
-    pub async fn new(pool_size: usize) -> Result<Supervisor> {
-        let auto_routing = true;
-        let mut workers = vec![];
-
-        for _ in 0..pool_size {
-            let worker = Worker::new().await;
-            workers.push(worker);
-        }
-
-        Ok(Supervisor {
-            pool_size,
-            auto_routing,
-            workers,
-        })
-    }
+    /// store the value (json blob)
+    pub async fn set(
+        &self,
+        key: String,
+        value: JsonString,
+    ) -> Result<Option<String>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let mut resp = self
+            .request(route, |tx| Command::Set(key.to_string(), value, tx))
+            .await?;
 
-    /// shut the workers down
-    pub async fn shutdown(&self) -> Result<()> {
-        for worker in self.workers.iter() {
-            info!("shut worker, id: {} down", worker.id());
-            let tx = worker.request_channel();
-            let r = tx.send(Command::Shutdown).await;
-            info!("ok? {:?}", r);
+        // the old copy must not be migrated over the new value
+        if let Some(old) = self.previous_owner(&key, Command::Remove).await? {
+            resp = resp.or(old);
         }
 
-        Ok(())
-    }
-
-    /// return the route number based on domain route-key logic and the worker pool size
-    pub fn get_route(&self, key: &str) -> usize {
-        if self.pool_size > 1 {
-            let rcount = self.pool_size as u8;
-            match RouteKey::parse_route(key, rcount) {
-                Ok(x) => x as usize,
-                _ => 0_usize,
-            }
-        } else {
-            0_usize
+        if let Some(json) = &resp {
+            info!("{}", json);
         }
-    }
 
-    /// store the value (json blob)
-    pub async fn set(&self, key: String, value: JsonString) -> Result<Option<String>> {
-        let route = self.get_route(&key);
-        let worker = &self.workers[route];
-
-        let worker_id = worker.id();
+        Ok(resp)
+    }
 
-        let request_channel = worker.request_channel();
-        let (responder, rx) = async_channel::bounded(10);
-        let msg = Command::Set(key, value, responder);
+    /// store the value (json blob); the entry expires after the ttl
+    pub async fn set_with_ttl(
+        &self,
+        key: String,
+        value: JsonString,
+        ttl: Duration,
+    ) -> Result<Option<String>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let mut resp = self
+            .request(route, |tx| {
+                Command::SetWithTtl(key.to_string(), value, ttl, tx)
+            })
+            .await?;
 
-        let resp = request_channel.send(msg).await;
-        if resp.is_err() {
-            let msg = format!("worker id {} request channel is down", worker_id);
-            error!("{}", msg);
-            return Err(anyhow!(msg));
+        if let Some(old) = self.previous_owner(&key, Command::Remove).await? {
+            resp = resp.or(old);
         }
 
-        let resp = if let Some(json) = rx.recv().await? {
+        if let Some(json) = &resp {
             info!("{}", json);
-            Some(json)
-        } else {
-            None
-        };
+        }
 
         Ok(resp)
     }
 
     /// store the value (json blob)
-    pub async fn get(&self, key: String) -> Result<Option<String>> {
-        let route = self.get_route(&key);
-        let worker = &self.workers[route];
-
-        let worker_id = worker.id();
+    pub async fn get(&self, key: String) -> Result<Option<String>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let mut resp = self
+            .request(route, |tx| Command::Get(key.to_string(), tx))
+            .await?;
 
-        let request_channel = worker.request_channel();
-        let (responder, rx) = async_channel::bounded(10);
-        let msg = Command::Get(key, responder);
-
-        let resp = request_channel.send(msg).await;
-        if resp.is_err() {
-            let msg = format!("worker id {} request channel is down", worker_id);
-            error!("{}", msg);
-            return Err(anyhow!(msg));
+        if resp.is_none() {
+            resp = self.previous_owner(&key, Command::Get).await?.flatten();
         }
 
-        let resp = if let Some(json) = rx.recv().await? {
+        if let Some(json) = &resp {
             info!("{}", json);
-            Some(json)
-        } else {
-            None
-        };
+        }
 
         Ok(resp)
     }
 
     /// remove the item by key and return the value if it exists
-    pub async fn remove(&self, key: String) -> Result<Option<String>> {
-        let route = self.get_route(&key);
-        let worker = &self.workers[route];
-
-        let request_channel = worker.request_channel();
-        let (responder, rx) = async_channel::bounded(2);
-        let msg = Command::Remove(key, responder);
-
-        let resp = request_channel.send(msg).await;
-        if resp.is_err() {
-            let msg = format!("worker id {} request channel is down", worker.id());
-            error!("{}", msg);
-            return Err(anyhow!(msg));
+    pub async fn remove(&self, key: String) -> Result<Option<String>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let mut resp = self
+            .request(route, |tx| Command::Remove(key.to_string(), tx))
+            .await?;
+
+        if let Some(old) = self.previous_owner(&key, Command::Remove).await? {
+            resp = resp.or(old);
         }
 
-        let resp = if let Some(json) = rx.recv().await? {
+        if let Some(json) = &resp {
             info!("{}", json);
-            Some(json)
-        } else {
-            None
-        };
+        }
 
         Ok(resp)
     }
 
-    /// return the status of each worker; if a worker is non-responsive, send worker down response.
-    /// NOTE: *good candidate for paralell ops...*
-    pub async fn status(&self) -> Vec<WorkerStatus> {
-        let mut status = vec![];
-        for worker in self.workers.iter() {
-            let ws = Self::worker_status(worker).await;
-
-            status.push(ws);
+    /// while the pool is resized, send the command to the worker that owned the key before the
+    /// resize; the entry may not have been migrated yet.  Return None if the owner is unchanged.
+    async fn previous_owner<R, C>(
+        &self,
+        key: &str,
+        command: C,
+    ) -> Result<Option<R>, SupervisorError>
+    where
+        C: FnOnce(String, Sender<R>) -> Command,
+    {
+        match self.previous_route(key) {
+            Some(route) => {
+                let resp = self
+                    .request(route, |tx| command(key.to_string(), tx))
+                    .await?;
+                Ok(Some(resp))
+            }
+            None => Ok(None),
         }
+    }
 
-        status
+    /// return the values for the keys in the same order as the keys.  The keys are grouped by
+    /// route and each worker gets a single batch request; the workers are queried concurrently.
+    pub async fn mget(&self, keys: Vec<String>) -> Result<Vec<Option<String>>, SupervisorError> {
+        self.batch(keys, |key| key, Command::MGet, Command::MGet)
+            .await
     }
 
-    async fn worker_status(worker: &Worker) -> WorkerStatus {
-        let request_channel = worker.request_channel();
-        let (responder, rx) = async_channel::bounded(10);
-        let msg = Command::Status(responder);
+    /// store the values (json blobs); return the previous values in the same order as the items
+    pub async fn mset(
+        &self,
+        items: Vec<(String, JsonString)>,
+    ) -> Result<Vec<Option<String>>, SupervisorError> {
+        self.batch(items, |(key, _)| key, Command::MSet, Command::MRemove)
+            .await
+    }
+
+    /// remove the keys; return the removed values in the same order as the keys
+    pub async fn mremove(&self, keys: Vec<String>) -> Result<Vec<Option<String>>, SupervisorError> {
+        self.batch(keys, |key| key, Command::MRemove, Command::MRemove)
+            .await
+    }
+
+    /// split the items into one batch per route, send the batches concurrently and put the
+    /// responses back in input order.  Any failed batch fails the whole request.  While the pool
+    /// is resized, the `previous` command is sent to the workers that owned the keys before the
+    /// resize and fills in the missing values.
+    async fn batch<T, K, C, P>(
+        &self,
+        items: Vec<T>,
+        key: K,
+        command: C,
+        previous: P,
+    ) -> Result<Vec<Option<String>>, SupervisorError>
+    where
+        K: Fn(&T) -> &String,
+        C: Fn(Vec<T>, Sender<Vec<Option<String>>>) -> Command,
+        P: Fn(Vec<String>, Sender<Vec<Option<String>>>) -> Command,
+    {
+        let _routing = self.routing_guard().await;
+
+        let count = items.len();
+        let mut batches: BTreeMap<usize, (Vec<usize>, Vec<T>)> = BTreeMap::new();
+        let mut moved: BTreeMap<usize, (Vec<usize>, Vec<String>)> = BTreeMap::new();
+        for (idx, item) in items.into_iter().enumerate() {
+            let route = self.get_route(key(&item))?;
+            if let Some(prev) = self.previous_route(key(&item)) {
+                let batch = moved.entry(prev).or_default();
+                batch.0.push(idx);
+                batch.1.push(key(&item).to_string());
+            }
 
-        let resp = request_channel.send(msg).await;
-        if resp.is_err() {
-            return WorkerStatus::worker_down(worker.id());
+            let batch = batches.entry(route).or_default();
+            batch.0.push(idx);
+            batch.1.push(item);
         }
 
-        if let Ok(json) = rx.recv().await {
-            serde_json::from_str(&json).expect("should always decode")
-        } else {
-            WorkerStatus::worker_down(worker.id())
+        let mut indexes = vec![];
+        let mut requests = vec![];
+        for (route, (idx, batch)) in batches {
+            indexes.push(idx);
+            requests.push(self.request(route, |tx| command(batch, tx)));
         }
-    }
 
-    /// return the keys from all workers
-    pub async fn keys(&self) -> Vec<String> {
-        let mut ks: Vec<String> = vec![];
+        let mut values = vec![None; count];
+        for (idx, resp) in indexes.into_iter().zip(join_all(requests).await) {
+            for (n, value) in idx.into_iter().zip(resp?) {
+                values[n] = value;
+            }
+        }
 
-        for worker in self.workers.iter() {
-            let list = Self::worker_keys(worker).await;
-            ks.extend(list.into_iter())
+        let mut indexes = vec![];
+        let mut requests = vec![];
+        for (route, (idx, keys)) in moved {
+            indexes.push(idx);
+            requests.push(self.request(route, |tx| previous(keys, tx)));
         }
 
-        ks
+        for (idx, resp) in indexes.into_iter().zip(join_all(requests).await) {
+            for (n, value) in idx.into_iter().zip(resp?) {
+                if values[n].is_none() {
+                    values[n] = value;
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// serialize and store the value; return the previous value decoded as the same type
+    pub async fn set_value<T>(&self, key: String, value: &T) -> Result<Option<T>, TypedError>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let json = encode(value)?;
+        let prev = self
+            .set(key.to_string(), json)
+            .await
+            .map_err(TypedError::Request)?;
+
+        decode(&key, prev)
     }
 
-    async fn worker_keys(worker: &Worker) -> Vec<String> {
-        let request_channel = worker.request_channel();
-        let (responder, rx) = async_channel::bounded(10);
-        let msg = Command::Keys(responder);
-        request_channel
-            .send(msg)
+    /// return the value for the key decoded from json
+    pub async fn get_value<T: DeserializeOwned>(
+        &self,
+        key: String,
+    ) -> Result<Option<T>, TypedError> {
+        let json = self
+            .get(key.to_string())
             .await
-            .expect("should always return");
+            .map_err(TypedError::Request)?;
 
-        let list = rx.recv().await.expect("should always return a size");
+        decode(&key, json)
+    }
 
-        println!("keys: {:?}", list);
+    /// return the remaining time to live for the key
+    pub async fn ttl(&self, key: String) -> Result<KeyTtl, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let ttl = self
+            .request(route, |tx| Command::Ttl(key.to_string(), tx))
+            .await?;
 
-        list
+        match ttl {
+            KeyTtl::Missing => Ok(self
+                .previous_owner(&key, Command::Ttl)
+                .await?
+                .unwrap_or(KeyTtl::Missing)),
+            _ => Ok(ttl),
+        }
     }
 
-    /// return the total number of entries from all workers
-    /// NOTE: *good candidate for paralell ops...*
-    pub async fn len(&self) -> usize {
-        let mut sz = 0_usize;
-        for worker in self.workers.iter() {
-            sz += Self::worker_len(worker).await;
+    /// remove the expiry from the key; return true if the key had one
+    pub async fn persist(&self, key: String) -> Result<bool, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let persisted = self
+            .request(route, |tx| Command::Persist(key.to_string(), tx))
+            .await?;
+
+        if persisted {
+            return Ok(true);
         }
 
-        sz
+        let prev = self.previous_owner(&key, Command::Persist).await?;
+        Ok(prev.unwrap_or(false))
+    }
+
+    /// store the value only if the key is missing; return true if it was stored
+    pub async fn set_nx(&self, key: String, value: JsonString) -> Result<bool, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.claim(&key).await?;
+        self.request(route, |tx| Command::SetNx(key, value, tx))
+            .await
     }
 
-    async fn worker_len(worker: &Worker) -> usize {
-        let request_channel = worker.request_channel();
-        let (responder, rx) = async_channel::bounded(10);
-        let msg = Command::Len(responder);
-        request_channel
-            .send(msg)
+    /// replace the value only if the key exists; return the previous value, or None if nothing
+    /// was stored
+    pub async fn set_xx(
+        &self,
+        key: String,
+        value: JsonString,
+    ) -> Result<Option<String>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.claim(&key).await?;
+        self.request(route, |tx| Command::SetXx(key, value, tx))
             .await
-            .expect("should always return");
+    }
+
+    /// replace the value only if it is still `expected`; return true if it was replaced.  The
+    /// key keeps its expiry.  Use it for read-modify-write updates without lost updates:
+    /// get the value, change it, then retry from the get until the swap succeeds.
+    pub async fn compare_and_swap(
+        &self,
+        key: String,
+        expected: JsonString,
+        value: JsonString,
+    ) -> Result<bool, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.claim(&key).await?;
+        self.request(route, |tx| {
+            Command::CompareAndSwap(key, expected, value, tx)
+        })
+        .await
+    }
+
+    /// add the delta to the key's integer value and return the result; a missing key starts at
+    /// zero and the key keeps its expiry.  A value that is not an integer, or a result that
+    /// overflows, returns `SupervisorError::Value`.
+    pub async fn incr_by(&self, key: String, delta: i64) -> Result<i64, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.claim(&key).await?;
+        self.request(route, |tx| Command::IncrBy(key.to_string(), delta, tx))
+            .await?
+            .map_err(|message| SupervisorError::Value { key, message })
+    }
+
+    /// subtract the delta from the key's integer value and return the result
+    pub async fn decr_by(&self, key: String, delta: i64) -> Result<i64, SupervisorError> {
+        match delta.checked_neg() {
+            Some(delta) => self.incr_by(key, delta).await,
+            None => Err(SupervisorError::Value {
+                key,
+                message: "increment or decrement would overflow".to_string(),
+            }),
+        }
+    }
+
+    /// append the text to the key's value and return the new length in bytes; a missing key
+    /// starts empty and the key keeps its expiry
+    pub async fn append(&self, key: String, suffix: String) -> Result<usize, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.claim(&key).await?;
+        self.request(route, |tx| Command::Append(key, suffix, tx))
+            .await
+    }
+
+    /// return the key's route.  While the pool is resized the entry is first moved from the
+    /// worker that owned the key before the resize, so an update reads the current value.
+    async fn claim(&self, key: &str) -> Result<usize, SupervisorError> {
+        let route = self.get_route(key)?;
+        let Some(prev) = self.previous_route(key) else {
+            return Ok(route);
+        };
+
+        let handoff = self
+            .request(prev, |tx| Command::Take(vec![key.to_string()], tx))
+            .await?;
+        if let Some(entries) = handoff.downcast::<Vec<(String, Entry)>>() {
+            if !entries.is_empty() {
+                self.request(route, |tx| Command::Put(entries, tx)).await?;
+            }
+        }
 
-        let sz = rx.recv().await.expect("should always return a size");
+        Ok(route)
+    }
+
+    /// return the keys from all workers.  If any worker fails the error holds the keys from the
+    /// workers that responded.  Use `scan` to page through a large cache.
+    pub async fn keys(&self) -> Result<Vec<String>, PartialError<Vec<String>>> {
+        let results = self.request_all(Command::Keys).await;
+        PartialError::combine(results, vec![], |ks, list| {
+            info!("keys: {:?}", list);
+            ks.extend(list)
+        })
+    }
+
+    /// return the total number of entries from all workers
+    pub async fn len(&self) -> Result<usize, PartialError<usize>> {
+        let results = self.request_all(Command::Len).await;
+        PartialError::combine(results, 0, |sz, n| *sz += n)
+    }
+
+    /// return true if none of the workers hold any entries
+    pub async fn is_empty(&self) -> Result<bool, PartialError<usize>> {
+        Ok(self.len().await? == 0)
+    }
 
-        sz
+    /// the hit and miss counts for each level of a tiered cache, summed across the workers
+    pub async fn tier_stats(&self) -> Vec<TierStats> {
+        let mut totals: Vec<TierStats> = vec![];
+        for status in self.status().await {
+            for stats in status.tiers {
+                match totals.iter_mut().find(|t| t.tier == stats.tier) {
+                    Some(total) => {
+                        total.hits += stats.hits;
+                        total.misses += stats.misses;
+                    }
+                    None => totals.push(stats),
+                }
+            }
+        }
+
+        totals
+    }
+
+    /// return a channel of the set, remove, expire and evict events for the keys that match the
+    /// glob pattern, e.g., `supervisor.subscribe("session:*")`.  The channel buffers up to 100
+    /// events and drops the oldest when the subscriber falls behind.
+    pub fn subscribe(&self, pattern: &str) -> Receiver<CacheEvent> {
+        self.subscribe_with(pattern, SubscribeOptions::default())
+    }
+
+    /// subscribe with the buffer size and slow subscriber policy; drop the receiver to
+    /// unsubscribe
+    pub fn subscribe_with(&self, pattern: &str, options: SubscribeOptions) -> Receiver<CacheEvent> {
+        self.worker_config()
+            .events
+            .subscribe(KeyPattern::new(pattern), options)
     }
 }
 
@@ -231,7 +429,23 @@ mod tests {
     use serde::{Deserialize, Serialize};
 
     use super::*;
+    use crate::cache::aof::{AofConfig, FsyncPolicy};
+    use crate::cache::backend::BackendConfig;
+    use crate::cache::clock::ManualClock;
+    use crate::cache::eviction::EvictionPolicy;
+    use crate::cache::file::FileConfig;
+    use crate::cache::redis::RedisConfig;
+    use crate::cache::stand_in::StandInServer;
+    use crate::cache::tiered::{TierConfig, WritePolicy};
+    use crate::cache::worker::CacheConfig;
+    use crate::supervisor::routing::{ConsistentHashRouter, PoolView, Router};
+    use crate::supervisor::{
+        Backpressure, HealthConfig, HealthEvent, RecycleConfig, RouteKeyRouter, SupervisorConfig,
+    };
     use crate::worker::{WorkerState, OK};
+    use async_std::task;
+    use domain_keys::keys::RouteKey;
+    use std::sync::Arc;
 
     #[derive(Debug, Default, Clone, Serialize, Deserialize)]
     pub struct TestStruct {
@@ -266,7 +480,7 @@ mod tests {
             let supervisor = Supervisor::new(pool_size)
                 .await
                 .expect("should create the supervisor");
-            assert_eq!(supervisor.workers.len(), pool_size);
+            assert_eq!(supervisor.workers().len(), pool_size);
 
             let status = supervisor.status().await;
             println!("{:?}", status);
@@ -280,7 +494,8 @@ mod tests {
                 assert_eq!(sts.error_count, 0);
             }
 
-            assert_eq!(supervisor.len().await, 0);
+            assert_eq!(supervisor.len().await, Ok(0));
+            assert_eq!(supervisor.is_empty().await, Ok(true));
 
             // set a number of of values
             let set_count: usize = 20;
@@ -289,7 +504,7 @@ mod tests {
                 let tst = TestStruct::new();
                 ids.push(tst.id.to_string());
                 assert_eq!(tst.id.len(), 16);
-                let route = supervisor.get_route(&tst.id);
+                let route = supervisor.get_route(&tst.id).unwrap();
                 println!("{:?} {}", tst, route);
                 assert!(route < pool_size);
 
@@ -302,7 +517,7 @@ mod tests {
             }
 
             assert_eq!(ids.len(), set_count);
-            assert_eq!(supervisor.len().await, set_count);
+            assert_eq!(supervisor.len().await, Ok(set_count));
 
             // now read them all back
             for id in ids.iter() {
@@ -316,7 +531,7 @@ mod tests {
                         serde_json::from_str(&json).expect("should be able to parse");
                     assert_eq!(tst.id, id.to_string());
                 } else {
-                    assert!(false, "should not bew None");
+                    panic!("should not bew None");
                 }
             }
 
@@ -345,7 +560,7 @@ mod tests {
             assert_eq!(tst.age, old_age);
 
             // read all the keys and compare to ids list
-            let list = supervisor.keys().await;
+            let list = supervisor.keys().await.expect("should return all keys");
             assert_eq!(list.len(), ids.len());
 
             // remove a few and verify the new count
@@ -354,13 +569,758 @@ mod tests {
                 let tst: TestStruct = serde_json::from_str(&json).unwrap();
                 assert_eq!(tst.id, key.to_string());
             } else {
-                assert!(false);
+                panic!("should return the removed value");
             }
 
-            assert_eq!(supervisor.len().await, ids.len() - 1);
+            assert_eq!(supervisor.len().await, Ok(ids.len() - 1));
 
             // shut down the supervisor
             assert!(supervisor.shutdown().await.is_ok());
         });
     }
+
+    #[test]
+    fn ttl() {
+        async_std::task::block_on(async move {
+            let clock = ManualClock::new(1_000_000);
+            let config = SupervisorConfig {
+                worker: CacheConfig {
+                    clock: Arc::new(clock.clone()),
+                    ..CacheConfig::default()
+                },
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+
+            let session = TestStruct::new();
+            let json = serde_json::to_string(&session).unwrap();
+            let ttl = Duration::from_secs(30);
+            let r = supervisor
+                .set_with_ttl(session.id.to_string(), json, ttl)
+                .await
+                .unwrap();
+            assert_eq!(r, None);
+
+            let key = session.id.to_string();
+            let r = supervisor.ttl(key.to_string()).await.unwrap();
+            assert_eq!(r, KeyTtl::Expires(ttl));
+
+            clock.advance(Duration::from_secs(31));
+            assert_eq!(
+                supervisor.ttl(key.to_string()).await.unwrap(),
+                KeyTtl::Missing
+            );
+            assert_eq!(supervisor.get(key.to_string()).await.unwrap(), None);
+            assert!(!supervisor.persist(key).await.unwrap());
+
+            // a ttl too large for the clock never expires
+            let r = supervisor.set_with_ttl("forever".to_string(), "x".to_string(), Duration::MAX);
+            assert_eq!(r.await, Ok(None));
+            clock.advance(Duration::from_secs(365 * 24 * 3600));
+            let value = supervisor.get("forever".to_string()).await;
+            assert_eq!(value, Ok(Some("x".to_string())));
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn subscribe() {
+        async_std::task::block_on(async move {
+            let clock = ManualClock::new(1_000_000);
+            let config = SupervisorConfig {
+                worker: CacheConfig {
+                    clock: Arc::new(clock.clone()),
+                    sweep_interval: Duration::from_millis(10),
+                    max_entries: Some(2),
+                    ..CacheConfig::default()
+                },
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(1, config).await.unwrap();
+            let rx = supervisor.subscribe("user:*");
+            let everything = supervisor.subscribe("*");
+
+            let key = |n: u32| format!("user:{}", n);
+            let ttl = Duration::from_secs(5);
+            assert!(supervisor.set(key(1), "a".to_string()).await.is_ok());
+            assert!(supervisor
+                .set("other".to_string(), "b".to_string())
+                .await
+                .is_ok());
+            assert!(supervisor.remove(key(1)).await.is_ok());
+            assert!(supervisor.remove(key(1)).await.is_ok());
+            assert!(supervisor
+                .set_with_ttl(key(2), "c".to_string(), ttl)
+                .await
+                .is_ok());
+            assert!(supervisor.set(key(3), "d".to_string()).await.is_ok());
+
+            // the expired entry is removed by the idle sweep
+            clock.advance(Duration::from_secs(10));
+            task::sleep(Duration::from_millis(50)).await;
+            assert!(supervisor.set(key(4), "e".to_string()).await.is_ok());
+
+            let expect = vec![
+                CacheEvent::Set {
+                    key: key(1),
+                    value: "a".to_string(),
+                },
+                CacheEvent::Remove { key: key(1) },
+                CacheEvent::Set {
+                    key: key(2),
+                    value: "c".to_string(),
+                },
+                // other is evicted to make room for user:3
+                CacheEvent::Set {
+                    key: key(3),
+                    value: "d".to_string(),
+                },
+                CacheEvent::Expire { key: key(2) },
+                CacheEvent::Set {
+                    key: key(4),
+                    value: "e".to_string(),
+                },
+            ];
+            let mut events = vec![];
+            while let Ok(event) = rx.try_recv() {
+                events.push(event);
+            }
+            assert_eq!(events, expect);
+
+            assert_eq!(everything.len(), expect.len() + 2);
+            let evicted = CacheEvent::Evict {
+                key: "other".to_string(),
+            };
+            let mut events = vec![];
+            while let Ok(event) = everything.try_recv() {
+                events.push(event);
+            }
+            assert!(events.contains(&evicted));
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn atomic() {
+        async_std::task::block_on(async move {
+            let supervisor = Supervisor::new(2).await.unwrap();
+            let key = || "counter".to_string();
+
+            assert_eq!(supervisor.set_xx(key(), "1".to_string()).await, Ok(None));
+            assert_eq!(supervisor.set_nx(key(), "1".to_string()).await, Ok(true));
+            assert_eq!(supervisor.set_nx(key(), "2".to_string()).await, Ok(false));
+            let prev = supervisor.set_xx(key(), "5".to_string()).await;
+            assert_eq!(prev, Ok(Some("1".to_string())));
+
+            // concurrent increments are never lost
+            let incrs = (0..50).map(|_| supervisor.incr_by(key(), 2));
+            let results = join_all(incrs).await;
+            assert!(results.iter().all(|r| r.is_ok()));
+            assert_eq!(supervisor.decr_by(key(), 5).await, Ok(100));
+            assert_eq!(supervisor.incr_by("new".to_string(), -3).await, Ok(-3));
+
+            // concurrent read-modify-write with compare and swap
+            let update = || async {
+                loop {
+                    let current = supervisor.get(key()).await.unwrap().unwrap();
+                    let next = (current.parse::<i64>().unwrap() * 2).to_string();
+                    if supervisor
+                        .compare_and_swap(key(), current, next)
+                        .await
+                        .unwrap()
+                    {
+                        break;
+                    }
+                }
+            };
+            join_all((0..3).map(|_| update())).await;
+            assert_eq!(supervisor.get(key()).await, Ok(Some("800".to_string())));
+
+            let name = || "name".to_string();
+            assert_eq!(supervisor.append(name(), "worker".to_string()).await, Ok(6));
+            assert_eq!(supervisor.append(name(), "-lib".to_string()).await, Ok(10));
+            let err = supervisor.incr_by(name(), 1).await.unwrap_err();
+            assert!(matches!(err, SupervisorError::Value { .. }));
+            assert!(supervisor.decr_by(key(), i64::MIN).await.is_err());
+            let r = supervisor.incr_by(key(), i64::MAX).await;
+            assert!(matches!(r, Err(SupervisorError::Value { .. })));
+
+            // updates keep the expiry
+            let ttl = Duration::from_secs(60);
+            assert!(supervisor
+                .set_with_ttl(key(), "1".to_string(), ttl)
+                .await
+                .is_ok());
+            assert_eq!(supervisor.incr_by(key(), 1).await, Ok(2));
+            assert!(matches!(
+                supervisor.ttl(key()).await,
+                Ok(KeyTtl::Expires(_))
+            ));
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn batch() {
+        async_std::task::block_on(async move {
+            let supervisor = Supervisor::new(4)
+                .await
+                .expect("should create the supervisor");
+
+            let items: Vec<(String, String)> = (0..40)
+                .map(|n| (RouteKey::create(), format!("{}", n)))
+                .collect();
+            let keys: Vec<String> = items.iter().map(|(k, _)| k.to_string()).collect();
+
+            let prev = supervisor.mset(items.clone()).await.unwrap();
+            assert_eq!(prev, vec![None; 40]);
+            assert_eq!(supervisor.len().await, Ok(40));
+
+            // results follow the input order, including missing keys
+            let mut request: Vec<String> = keys.iter().rev().cloned().collect();
+            request.insert(5, "missing".to_string());
+            let values = supervisor.mget(request).await.unwrap();
+            assert_eq!(values.len(), 41);
+            assert_eq!(values[0], Some("39".to_string()));
+            assert_eq!(values[5], None);
+            assert_eq!(values[40], Some("0".to_string()));
+
+            let update = vec![(keys[3].to_string(), "x".to_string())];
+            let prev = supervisor.mset(update).await.unwrap();
+            assert_eq!(prev, vec![Some("3".to_string())]);
+
+            let removed = supervisor.mremove(keys[..10].to_vec()).await.unwrap();
+            assert_eq!(removed[3], Some("x".to_string()));
+            assert_eq!(removed[9], Some("9".to_string()));
+            assert_eq!(supervisor.len().await, Ok(30));
+            assert_eq!(supervisor.mget(vec![]).await, Ok(vec![]));
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn routing() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                router: Arc::new(RouteKeyRouter::strict()),
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(300, config)
+                .await
+                .expect("should create the supervisor");
+
+            let err = supervisor
+                .set("user:1".to_string(), "{}".to_string())
+                .await
+                .expect_err("should not route");
+            assert!(matches!(err, SupervisorError::Route { .. }));
+
+            let items: Vec<(String, String)> = (0..1_000)
+                .map(|_| (RouteKey::create(), "{}".to_string()))
+                .collect();
+            assert!(supervisor.mset(items).await.is_ok());
+
+            // workers past route 255 get entries
+            let counts = supervisor.request_all(Command::Len).await;
+            let high: usize = counts[256..].iter().map(|r| *r.as_ref().unwrap()).sum();
+            assert!(high > 0);
+            assert_eq!(supervisor.len().await, Ok(1_000));
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn resize() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                router: Arc::new(ConsistentHashRouter::default()),
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+
+            let items: Vec<(String, String)> = (0..500)
+                .map(|n| (format!("key-{}", n), format!("{}", n)))
+                .collect();
+            let keys: Vec<String> = items.iter().map(|(k, _)| k.to_string()).collect();
+            assert!(supervisor.mset(items).await.is_ok());
+            let ttl = Duration::from_secs(600);
+            let r = supervisor.set_with_ttl("expires".to_string(), "x".to_string(), ttl);
+            assert!(r.await.is_ok());
+
+            // reads during the migration see every key
+            let reads = async {
+                for key in keys.iter().step_by(7) {
+                    let value = supervisor.get(key.to_string()).await.unwrap();
+                    assert!(value.is_some(), "key {} should be readable", key);
+                }
+            };
+            let (resized, _) = futures::join!(supervisor.resize(5), reads);
+            assert!(resized.is_ok());
+            assert_eq!(supervisor.pool_size(), 5);
+            assert_eq!(supervisor.previous_size(), None);
+
+            let counts = supervisor.request_all(Command::Len).await;
+            assert!(counts.iter().all(|r| *r.as_ref().unwrap() > 0));
+            assert_eq!(supervisor.len().await, Ok(501));
+            let values = supervisor.mget(keys.to_vec()).await.unwrap();
+            assert!(values.iter().all(|v| v.is_some()));
+            assert_eq!(values[42], Some("42".to_string()));
+            match supervisor.ttl("expires".to_string()).await.unwrap() {
+                KeyTtl::Expires(t) => assert!(t <= ttl),
+                other => panic!("should keep the expiry: {:?}", other),
+            }
+
+            // shrink; the drained workers hand over their entries
+            let drained = supervisor.worker(4).unwrap();
+            assert!(supervisor.resize(3).await.is_ok());
+            assert_eq!(supervisor.workers().len(), 3);
+            assert_eq!(supervisor.len().await, Ok(501));
+            let values = supervisor.mget(keys.to_vec()).await.unwrap();
+            assert!(values.iter().all(|v| v.is_some()));
+            task::sleep(Duration::from_millis(20)).await;
+            assert!(drained.request_channel().is_closed());
+
+            assert_eq!(
+                supervisor.resize(0).await,
+                Err(SupervisorError::InvalidSize(0))
+            );
+            assert!(supervisor.shutdown().await.is_ok());
+            assert_eq!(supervisor.resize(4).await, Err(SupervisorError::Shutdown));
+        });
+    }
+
+    /// fails to route the poison keys at any size but 2
+    #[derive(Debug, Default)]
+    struct PoisonRouter(ConsistentHashRouter);
+
+    impl Router for PoisonRouter {
+        fn route(&self, key: &str, pool: &PoolView) -> Result<usize, SupervisorError> {
+            if key.starts_with("poison") && pool.size() != 2 {
+                let message = "poisoned".to_string();
+                return Err(SupervisorError::Route {
+                    key: key.to_string(),
+                    message,
+                });
+            }
+            self.0.route(key, pool)
+        }
+    }
+
+    #[test]
+    fn resize_failure() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                router: Arc::new(PoisonRouter::default()),
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+
+            // the poison key is on the last route so the first route migrates before the error
+            let poison = (0..)
+                .map(|n| format!("poison-{}", n))
+                .find(|key| supervisor.get_route(key) == Ok(1))
+                .unwrap();
+            let mut items: Vec<(String, String)> = (0..200)
+                .map(|n| (format!("key-{}", n), format!("{}", n)))
+                .collect();
+            items.push((poison.to_string(), "p".to_string()));
+            let keys: Vec<String> = items.iter().map(|(k, _)| k.to_string()).collect();
+            assert!(supervisor.mset(items).await.is_ok());
+
+            for size in [4, 1] {
+                let err = supervisor.resize(size).await.unwrap_err();
+                assert!(matches!(err, SupervisorError::Route { .. }));
+                assert_eq!(supervisor.pool_size(), 2);
+                assert_eq!(supervisor.previous_size(), None);
+                assert_eq!(supervisor.workers().len(), 2);
+
+                // every entry is back with its old owner
+                let counts = supervisor.request_all(Command::Len).await;
+                assert!(counts.iter().all(|r| *r.as_ref().unwrap() > 0));
+                assert_eq!(supervisor.len().await, Ok(201));
+                let values = supervisor.mget(keys.to_vec()).await.unwrap();
+                assert!(values.iter().all(|v| v.is_some()));
+            }
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn aof() {
+        async_std::task::block_on(async move {
+            let dir = std::env::temp_dir().join(format!("worker-lib-cache-{}", fastrand::u64(..)));
+            let config = || SupervisorConfig {
+                router: Arc::new(ConsistentHashRouter::default()),
+                worker: CacheConfig {
+                    aof: Some(AofConfig {
+                        fsync: FsyncPolicy::Always,
+                        ..AofConfig::new(&dir)
+                    }),
+                    ..CacheConfig::default()
+                },
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(3, config()).await.unwrap();
+
+            let items: Vec<(String, String)> = (0..50)
+                .map(|n| (format!("key-{}", n), format!("{}", n)))
+                .collect();
+            assert!(supervisor.mset(items).await.is_ok());
+            let ttl = Duration::from_secs(600);
+            let r = supervisor.set_with_ttl("expires".to_string(), "x".to_string(), ttl);
+            assert!(r.await.is_ok());
+            let r = supervisor.set_with_ttl("persist".to_string(), "y".to_string(), ttl);
+            assert!(r.await.is_ok());
+            assert_eq!(supervisor.persist("persist".to_string()).await, Ok(true));
+            assert!(supervisor.remove("key-7".to_string()).await.is_ok());
+            let removed = vec!["key-8".to_string(), "key-9".to_string()];
+            assert!(supervisor.mremove(removed).await.is_ok());
+            assert!(supervisor.shutdown().await.is_ok());
+            task::sleep(Duration::from_millis(50)).await;
+
+            // a new pool replays the logs
+            let supervisor = Supervisor::with_config(3, config()).await.unwrap();
+            assert_eq!(supervisor.len().await, Ok(49));
+            let value = supervisor.get("key-42".to_string()).await.unwrap();
+            assert_eq!(value, Some("42".to_string()));
+            assert_eq!(supervisor.get("key-7".to_string()).await, Ok(None));
+            assert_eq!(supervisor.get("key-9".to_string()).await, Ok(None));
+            match supervisor.ttl("expires".to_string()).await.unwrap() {
+                KeyTtl::Expires(t) => assert!(t <= ttl),
+                other => panic!("should keep the expiry: {:?}", other),
+            }
+            assert_eq!(
+                supervisor.ttl("persist".to_string()).await,
+                Ok(KeyTtl::Persistent)
+            );
+
+            // the logs follow the entries when the pool is resized
+            assert!(supervisor.resize(5).await.is_ok());
+            assert!(supervisor.shutdown().await.is_ok());
+            task::sleep(Duration::from_millis(50)).await;
+
+            let supervisor = Supervisor::with_config(5, config()).await.unwrap();
+            assert_eq!(supervisor.len().await, Ok(49));
+            let value = supervisor.get("key-42".to_string()).await.unwrap();
+            assert_eq!(value, Some("42".to_string()));
+            assert!(supervisor.shutdown().await.is_ok());
+
+            let _ = async_std::fs::remove_dir_all(&dir).await;
+        });
+    }
+
+    #[test]
+    fn redis_backend() {
+        async_std::task::block_on(async move {
+            let server = StandInServer::start().await.unwrap();
+            let config = SupervisorConfig {
+                router: Arc::new(ConsistentHashRouter::default()),
+                worker: CacheConfig {
+                    backend: BackendConfig::Redis(RedisConfig::new(server.addr())),
+                    ..CacheConfig::default()
+                },
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(2, config).await.unwrap();
+
+            let items: Vec<(String, String)> = (0..40)
+                .map(|n| (format!("key-{}", n), format!("{}", n)))
+                .collect();
+            let keys: Vec<String> = items.iter().map(|(k, _)| k.to_string()).collect();
+            assert!(supervisor.mset(items).await.is_ok());
+            let ttl = Duration::from_secs(60);
+            let r = supervisor.set_with_ttl("session".to_string(), "s".to_string(), ttl);
+            assert_eq!(r.await, Ok(None));
+            assert_eq!(server.len(), 41);
+
+            assert_eq!(supervisor.len().await, Ok(41));
+            let value = supervisor.get("key-3".to_string()).await;
+            assert_eq!(value, Ok(Some("3".to_string())));
+            assert!(matches!(
+                supervisor.ttl("session".to_string()).await,
+                Ok(KeyTtl::Expires(_))
+            ));
+            let removed = supervisor.remove("key-3".to_string()).await;
+            assert_eq!(removed, Ok(Some("3".to_string())));
+
+            // the entries move between the worker prefixes
+            assert!(supervisor.resize(3).await.is_ok());
+            assert_eq!(supervisor.len().await, Ok(40));
+            assert_eq!(server.len(), 40);
+            let values = supervisor.mget(keys).await.unwrap();
+            assert_eq!(values.iter().filter(|v| v.is_some()).count(), 39);
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn tiered() {
+        async_std::task::block_on(async move {
+            let dir = std::env::temp_dir().join(format!("worker-lib-tier-{}", fastrand::u64(..)));
+            let config = || SupervisorConfig {
+                router: Arc::new(ConsistentHashRouter::default()),
+                worker: CacheConfig {
+                    max_entries: Some(10),
+                    l2: Some(TierConfig {
+                        write: WritePolicy::Behind,
+                        flush_interval: Duration::from_millis(20),
+                        ..TierConfig::new(BackendConfig::File(FileConfig::new(&dir)))
+                    }),
+                    ..CacheConfig::default()
+                },
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(2, config()).await.unwrap();
+
+            // more entries than L1 holds; the rest are read through from L2
+            let items: Vec<(String, String)> = (0..30)
+                .map(|n| (format!("key-{}", n), format!("{}", n)))
+                .collect();
+            let keys: Vec<String> = items.iter().map(|(k, _)| k.to_string()).collect();
+            assert!(supervisor.mset(items).await.is_ok());
+            assert_eq!(supervisor.len().await, Ok(30));
+            let values = supervisor.mget(keys.to_vec()).await.unwrap();
+            assert!(values.iter().all(|v| v.is_some()));
+            let value = supervisor.get("missing".to_string()).await;
+            assert_eq!(value, Ok(None));
+
+            let stats = supervisor.tier_stats().await;
+            assert_eq!(stats.len(), 2);
+            assert_eq!(stats[0].tier, "l1");
+            assert_eq!(stats[0].hits + stats[0].misses, 31);
+            assert!(stats[0].misses >= 10);
+            assert_eq!(stats[1].hits, stats[0].misses - 1);
+            assert_eq!(stats[1].misses, 1);
+
+            // the buffered writes reach the files and a new pool reads them through
+            assert!(supervisor.remove("key-1".to_string()).await.is_ok());
+            task::sleep(Duration::from_millis(50)).await;
+            assert!(supervisor.shutdown().await.is_ok());
+            task::sleep(Duration::from_millis(20)).await;
+
+            let supervisor = Supervisor::with_config(2, config()).await.unwrap();
+            let value = supervisor.get("key-29".to_string()).await;
+            assert_eq!(value, Ok(Some("29".to_string())));
+            assert_eq!(supervisor.get("key-1".to_string()).await, Ok(None));
+            assert_eq!(supervisor.len().await, Ok(29));
+            assert!(supervisor.shutdown().await.is_ok());
+
+            let _ = async_std::fs::remove_dir_all(&dir).await;
+        });
+    }
+
+    #[test]
+    fn aof_evictions() {
+        async_std::task::block_on(async move {
+            let dir = std::env::temp_dir().join(format!("worker-lib-evict-{}", fastrand::u64(..)));
+            let config = || SupervisorConfig {
+                worker: CacheConfig {
+                    max_entries: Some(3),
+                    eviction: EvictionPolicy::Lfu,
+                    aof: Some(AofConfig {
+                        fsync: FsyncPolicy::Always,
+                        ..AofConfig::new(&dir)
+                    }),
+                    ..CacheConfig::default()
+                },
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(1, config()).await.unwrap();
+
+            // reads make c the least frequently used, so d evicts it
+            for key in ["a", "b", "c"] {
+                assert!(supervisor
+                    .set(key.to_string(), key.to_string())
+                    .await
+                    .is_ok());
+            }
+            for key in ["a", "a", "b"] {
+                assert!(supervisor.get(key.to_string()).await.is_ok());
+            }
+            assert!(supervisor
+                .set("d".to_string(), "d".to_string())
+                .await
+                .is_ok());
+            assert_eq!(supervisor.get("c".to_string()).await, Ok(None));
+            assert!(supervisor
+                .shutdown_graceful(Duration::from_secs(1))
+                .await
+                .is_clean());
+
+            // the replay keeps the same keys, the reads are not in the log
+            let supervisor = Supervisor::with_config(1, config()).await.unwrap();
+            assert_eq!(supervisor.len().await, Ok(3));
+            for key in ["a", "b", "d"] {
+                let value = supervisor.get(key.to_string()).await;
+                assert_eq!(value, Ok(Some(key.to_string())));
+            }
+            assert_eq!(supervisor.get("c".to_string()).await, Ok(None));
+            assert!(supervisor.shutdown().await.is_ok());
+
+            let _ = async_std::fs::remove_dir_all(&dir).await;
+        });
+    }
+
+    #[test]
+    fn recycle() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                health: Some(HealthConfig {
+                    interval: Duration::from_millis(20),
+                    deadline: Duration::from_millis(100),
+                    max_missed: 3,
+                }),
+                recycle: RecycleConfig {
+                    max_commands: Some(10),
+                    ..RecycleConfig::default()
+                },
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(1, config).await.unwrap();
+            let events = supervisor.subscribe_health();
+            let old_id = supervisor.worker(0).unwrap().id();
+
+            for n in 0..10 {
+                let key = format!("key:{}", n);
+                assert!(supervisor.set(key, n.to_string()).await.is_ok());
+            }
+
+            let event = async_std::future::timeout(Duration::from_secs(2), events.recv())
+                .await
+                .expect("should get a health event")
+                .unwrap();
+            let new_id = supervisor.worker(0).unwrap().id();
+            match event {
+                HealthEvent::Recycled {
+                    route,
+                    worker_id,
+                    new_worker_id,
+                    ..
+                } => {
+                    assert_eq!(route, 0);
+                    assert_eq!(worker_id, old_id);
+                    assert_eq!(new_worker_id, new_id);
+                }
+                event => panic!("unexpected event: {:?}", event),
+            }
+            assert_eq!(supervisor.restart_count(0), 1);
+
+            // the entries were handed to the new worker
+            for n in 0..10 {
+                let value = supervisor.get(format!("key:{}", n)).await.unwrap();
+                assert_eq!(value, Some(n.to_string()));
+            }
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn recycle_aof() {
+        async_std::task::block_on(async move {
+            let dir =
+                std::env::temp_dir().join(format!("worker-lib-recycle-{}", fastrand::u64(..)));
+            let aof = AofConfig {
+                fsync: FsyncPolicy::Always,
+                ..AofConfig::new(&dir)
+            };
+            let worker = CacheConfig {
+                aof: Some(aof.clone()),
+                ..CacheConfig::default()
+            };
+            let config = SupervisorConfig {
+                health: Some(HealthConfig {
+                    interval: Duration::from_millis(20),
+                    deadline: Duration::from_millis(100),
+                    max_missed: 3,
+                }),
+                recycle: RecycleConfig {
+                    max_commands: Some(10),
+                    ..RecycleConfig::default()
+                },
+                worker: worker.clone(),
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(1, config).await.unwrap();
+            let events = supervisor.subscribe_health();
+            let old_id = supervisor.worker(0).unwrap().id();
+
+            // a directory in the way of the new log makes the adopt fail
+            let blocked = aof.path(0).with_extension("aof.handoff");
+            async_std::fs::create_dir_all(&blocked).await.unwrap();
+            for n in 0..10 {
+                let key = format!("key:{}", n);
+                assert!(supervisor.set(key, n.to_string()).await.is_ok());
+            }
+            task::sleep(Duration::from_millis(150)).await;
+
+            // the old worker stays in place with every entry
+            assert_eq!(supervisor.worker(0).unwrap().id(), old_id);
+            assert_eq!(supervisor.restart_count(0), 0);
+            for n in 0..10 {
+                let value = supervisor.get(format!("key:{}", n)).await.unwrap();
+                assert_eq!(value, Some(n.to_string()));
+            }
+
+            async_std::fs::remove_dir(&blocked).await.unwrap();
+            let event = async_std::future::timeout(Duration::from_secs(2), events.recv())
+                .await
+                .expect("should get a health event")
+                .unwrap();
+            assert!(matches!(event, HealthEvent::Recycled { .. }));
+            assert_ne!(supervisor.worker(0).unwrap().id(), old_id);
+            assert!(supervisor
+                .set("after".to_string(), "x".to_string())
+                .await
+                .is_ok());
+            assert!(supervisor
+                .shutdown_graceful(Duration::from_secs(1))
+                .await
+                .is_clean());
+
+            // the new worker's log holds the old entries and its own writes
+            let config = SupervisorConfig {
+                worker,
+                ..SupervisorConfig::default()
+            };
+            let supervisor = Supervisor::with_config(1, config).await.unwrap();
+            assert_eq!(supervisor.len().await, Ok(11));
+            for n in 0..10 {
+                let value = supervisor.get(format!("key:{}", n)).await.unwrap();
+                assert_eq!(value, Some(n.to_string()));
+            }
+            assert_eq!(
+                supervisor.get("after".to_string()).await,
+                Ok(Some("x".to_string()))
+            );
+            assert!(supervisor.shutdown().await.is_ok());
+
+            let _ = async_std::fs::remove_dir_all(&dir).await;
+        });
+    }
+
+    #[test]
+    fn shed_rejected() {
+        async_std::task::block_on(async move {
+            // a keyed request shed to another worker would miss its entry
+            let config = SupervisorConfig {
+                backpressure: Backpressure::Shed,
+                ..SupervisorConfig::default()
+            };
+            assert!(Supervisor::with_config(2, config).await.is_err());
+        });
+    }
 }
@@ -0,0 +1,451 @@
+/// the tiered cache backend; the in-memory store in front of a slower second level
+use crate::cache::backend::{BackendConfig, BackendFuture, CacheBackend};
+use crate::cache::clock;
+use crate::cache::store::{Entry, Removal};
+use crate::cache::worker::KeyTtl;
+use crate::worker::TierStats;
+use hashbrown::{HashMap, HashSet};
+use std::time::Duration;
+
+/// when writes reach the second level
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// write both levels before responding
+    #[default]
+    Through,
+    /// write L1 and buffer the change for the next flush to L2
+    Behind,
+}
+
+/// second level options
+#[derive(Debug, Clone)]
+pub struct TierConfig {
+    /// the L2 store; the file and Redis backends keep a store for each worker route
+    pub backend: BackendConfig,
+    pub write: WritePolicy,
+    /// how often buffered writes are flushed to L2 with the write-behind policy
+    pub flush_interval: Duration,
+}
+
+impl TierConfig {
+    pub fn new(backend: BackendConfig) -> TierConfig {
+        TierConfig {
+            backend,
+            write: WritePolicy::default(),
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug)]
+pub struct TieredBackend {
+    l1: Box<dyn CacheBackend>,
+    l2: Box<dyn CacheBackend>,
+    write: WritePolicy,
+    flush_interval: Duration,
+    /// write-behind changes not yet in L2; None is a pending remove
+    pending: HashMap<String, Option<Entry>>,
+    l1_counts: Counts,
+    l2_counts: Counts,
+}
+
+impl TieredBackend {
+    pub fn new(
+        l1: Box<dyn CacheBackend>,
+        l2: Box<dyn CacheBackend>,
+        config: &TierConfig,
+    ) -> TieredBackend {
+        TieredBackend {
+            l1,
+            l2,
+            write: config.write,
+            flush_interval: config.flush_interval,
+            pending: HashMap::new(),
+            l1_counts: Counts::default(),
+            l2_counts: Counts::default(),
+        }
+    }
+
+    /// the number of write-behind changes waiting for the next flush
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// the entry from the write-behind buffer, or from L2 with its expiry
+    async fn lower(&mut self, key: &str, now: u64) -> std::io::Result<Option<Entry>> {
+        if let Some(pending) = self.pending.get(key) {
+            return Ok(pending.clone().filter(|e| !e.is_expired(now)));
+        }
+
+        let value = match self.l2.get(key, now).await? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let expires_at = match self.l2.ttl(key, now).await? {
+            KeyTtl::Expires(ttl) => Some(clock::expires_at(now, ttl)),
+            _ => None,
+        };
+
+        Ok(Some(Entry::new(value, expires_at)))
+    }
+
+    /// send the change to L2 now or buffer it
+    async fn write_lower(
+        &mut self,
+        key: String,
+        entry: Option<Entry>,
+        now: u64,
+    ) -> std::io::Result<()> {
+        match (self.write, entry) {
+            (WritePolicy::Behind, entry) => {
+                self.pending.insert(key, entry);
+            }
+            (WritePolicy::Through, Some(entry)) => {
+                self.l2.insert(key, entry, now).await?;
+            }
+            (WritePolicy::Through, None) => {
+                self.l2.remove(&key, now).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CacheBackend for TieredBackend {
+    fn get<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            if let Some(value) = self.l1.get(key, now).await? {
+                self.l1_counts.hits += 1;
+                return Ok(Some(value));
+            }
+            self.l1_counts.misses += 1;
+
+            match self.lower(key, now).await? {
+                Some(entry) => {
+                    self.l2_counts.hits += 1;
+                    let value = entry.value.to_string();
+                    self.l1.insert(key.to_string(), entry, now).await?;
+                    Ok(Some(value))
+                }
+                None => {
+                    self.l2_counts.misses += 1;
+                    Ok(None)
+                }
+            }
+        })
+    }
+
+    fn insert(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, Option<String>> {
+        Box::pin(async move {
+            let mut prev = self.l1.insert(key.to_string(), entry.clone(), now).await?;
+            if prev.is_none() {
+                prev = self.lower(&key, now).await?.map(|e| e.value);
+            }
+            self.write_lower(key, Some(entry), now).await?;
+
+            Ok(prev)
+        })
+    }
+
+    fn remove<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let mut prev = self.l1.remove(key, now).await?;
+            if prev.is_none() {
+                prev = self.lower(key, now).await?.map(|e| e.value);
+            }
+            self.write_lower(key.to_string(), None, now).await?;
+
+            Ok(prev)
+        })
+    }
+
+    fn ttl<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, KeyTtl> {
+        Box::pin(async move {
+            match self.l1.ttl(key, now).await? {
+                KeyTtl::Missing => {
+                    let ttl = match self.lower(key, now).await? {
+                        Some(Entry {
+                            expires_at: Some(t),
+                            ..
+                        }) => KeyTtl::Expires(Duration::from_millis(t.saturating_sub(now))),
+                        Some(_) => KeyTtl::Persistent,
+                        None => KeyTtl::Missing,
+                    };
+                    Ok(ttl)
+                }
+                ttl => Ok(ttl),
+            }
+        })
+    }
+
+    fn persist<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, bool> {
+        Box::pin(async move {
+            // bring the entry into L1 so both levels end up the same
+            if self.get(key, now).await?.is_none() {
+                return Ok(false);
+            }
+            if !self.l1.persist(key, now).await? {
+                return Ok(false);
+            }
+
+            match self.write {
+                WritePolicy::Through => {
+                    self.l2.persist(key, now).await?;
+                }
+                WritePolicy::Behind => {
+                    let entry = self.l1.get(key, now).await?.map(|v| Entry::new(v, None));
+                    self.pending.insert(key.to_string(), entry);
+                }
+            }
+
+            Ok(true)
+        })
+    }
+
+    fn take<'a>(&'a mut self, key: &'a str, now: u64) -> BackendFuture<'a, Option<Entry>> {
+        Box::pin(async move {
+            let upper = self.l1.take(key, now).await?;
+            let pending = self.pending.remove(key);
+            let lower = self.l2.take(key, now).await?;
+
+            let entry = match pending {
+                Some(pending) => upper.or(pending),
+                None => upper.or(lower),
+            };
+            Ok(entry.filter(|e| !e.is_expired(now)))
+        })
+    }
+
+    fn put(&mut self, key: String, entry: Entry, now: u64) -> BackendFuture<'_, bool> {
+        Box::pin(async move {
+            // look without counting a hit or miss or filling L1; a handoff is not a read
+            if entry.is_expired(now)
+                || self.l1.get(&key, now).await?.is_some()
+                || self.lower(&key, now).await?.is_some()
+            {
+                return Ok(false);
+            }
+
+            self.l1.insert(key.to_string(), entry.clone(), now).await?;
+            self.write_lower(key, Some(entry), now).await?;
+            Ok(true)
+        })
+    }
+
+    /// the L2 keys with the write-behind buffer applied; L1 only holds keys that are in one of
+    /// them
+    fn keys(&mut self, now: u64) -> BackendFuture<'_, Vec<String>> {
+        Box::pin(async move {
+            let mut keys: HashSet<String> = self.l2.keys(now).await?.into_iter().collect();
+            for (key, entry) in self.pending.iter() {
+                match entry {
+                    Some(entry) if !entry.is_expired(now) => keys.insert(key.to_string()),
+                    _ => keys.remove(key),
+                };
+            }
+
+            Ok(keys.into_iter().collect())
+        })
+    }
+
+    /// the L2 entries with the write-behind buffer applied; L1 may hold newer copies
+    fn entries(&mut self, now: u64) -> BackendFuture<'_, Vec<(String, Entry)>> {
+        Box::pin(async move {
+            let mut entries: HashMap<String, Entry> =
+                self.l2.entries(now).await?.into_iter().collect();
+            for (key, entry) in self.pending.iter() {
+                match entry {
+                    Some(entry) if !entry.is_expired(now) => {
+                        entries.insert(key.to_string(), entry.clone());
+                    }
+                    _ => {
+                        entries.remove(key);
+                    }
+                }
+            }
+            entries.extend(self.l1.entries(now).await?);
+
+            Ok(entries.into_iter().collect())
+        })
+    }
+
+    /// the L2 count corrected for the buffered keys, without reading all of L2
+    fn len(&mut self, now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(async move {
+            let mut len = self.l2.len(now).await?;
+            for (key, entry) in self.pending.iter() {
+                let stored = self.l2.ttl(key, now).await? != KeyTtl::Missing;
+                let live = entry.as_ref().map_or(false, |e| !e.is_expired(now));
+                match (stored, live) {
+                    (false, true) => len += 1,
+                    (true, false) => len = len.saturating_sub(1),
+                    _ => {}
+                }
+            }
+
+            Ok(len)
+        })
+    }
+
+    fn sweep(&mut self, now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(async move {
+            let count = self.l1.sweep(now).await?;
+            self.l2.sweep(now).await?;
+            Ok(count)
+        })
+    }
+
+    fn evictions(&self) -> u64 {
+        self.l1.evictions()
+    }
+
+    fn flush(&mut self, now: u64) -> BackendFuture<'_, usize> {
+        Box::pin(async move {
+            let mut changes = self.pending.drain().collect::<Vec<_>>().into_iter();
+            let mut count = 0;
+            while let Some((key, entry)) = changes.next() {
+                let result = match entry.as_ref() {
+                    Some(e) => self.l2.insert(key.to_string(), e.clone(), now).await,
+                    None => self.l2.remove(&key, now).await,
+                };
+
+                // keep what is not yet written for the next flush
+                if let Err(e) = result {
+                    self.pending.insert(key, entry);
+                    self.pending.extend(changes);
+                    return Err(e);
+                }
+                count += 1;
+            }
+
+            Ok(count)
+        })
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        match self.write {
+            WritePolicy::Behind => Some(self.flush_interval),
+            WritePolicy::Through => None,
+        }
+    }
+
+    fn stats(&self) -> Vec<TierStats> {
+        vec![
+            TierStats::new("l1", self.l1_counts.hits, self.l1_counts.misses),
+            TierStats::new("l2", self.l2_counts.hits, self.l2_counts.misses),
+        ]
+    }
+
+    /// only expiries are reported; an entry evicted from L1 is still in the cache
+    fn drain_removals(&mut self) -> Vec<Removal> {
+        self.l2.drain_removals();
+        self.l1
+            .drain_removals()
+            .into_iter()
+            .filter(|r| matches!(r, Removal::Expired(_)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::eviction::EvictionPolicy;
+    use crate::cache::store::MemoryStore;
+
+    fn tiered(write: WritePolicy) -> TieredBackend {
+        let l1 = MemoryStore::new(Some(2), None, EvictionPolicy::Lru);
+        let config = TierConfig {
+            write,
+            ..TierConfig::new(BackendConfig::Memory)
+        };
+        TieredBackend::new(Box::new(l1), Box::new(MemoryStore::default()), &config)
+    }
+
+    fn entry(value: &str) -> Entry {
+        Entry::new(value.to_string(), None)
+    }
+
+    #[test]
+    fn write_through() {
+        async_std::task::block_on(async move {
+            let mut store = tiered(WritePolicy::Through);
+            assert_eq!(store.flush_interval(), None);
+            for key in ["a", "b", "c"] {
+                store.insert(key.to_string(), entry(key), 0).await.unwrap();
+            }
+
+            // "a" was evicted from L1 and is read through from L2
+            assert_eq!(store.l1.len(0).await.unwrap(), 2);
+            assert_eq!(store.l2.len(0).await.unwrap(), 3);
+            assert_eq!(store.get("a", 0).await.unwrap(), Some("a".to_string()));
+            assert_eq!(store.get("a", 0).await.unwrap(), Some("a".to_string()));
+            assert_eq!(store.get("x", 0).await.unwrap(), None);
+            let prev = store.insert("b".to_string(), entry("b2"), 0).await.unwrap();
+            assert_eq!(prev, Some("b".to_string()));
+
+            let stats = store.stats();
+            assert_eq!(stats[0], TierStats::new("l1", 1, 2));
+            assert_eq!(stats[1], TierStats::new("l2", 1, 1));
+            assert_eq!(stats[0].hit_ratio(), 1.0 / 3.0);
+
+            assert_eq!(store.remove("c", 0).await.unwrap(), Some("c".to_string()));
+            assert_eq!(store.l2.len(0).await.unwrap(), 2);
+            assert_eq!(store.len(0).await.unwrap(), 2);
+
+            // a handoff put is not a read; the counts stay the same
+            assert!(!store.put("a".to_string(), entry("a2"), 0).await.unwrap());
+            assert!(store.put("d".to_string(), entry("d"), 0).await.unwrap());
+            assert_eq!(store.stats(), stats);
+        });
+    }
+
+    #[test]
+    fn write_behind() {
+        async_std::task::block_on(async move {
+            let mut store = tiered(WritePolicy::Behind);
+            assert_eq!(store.flush_interval(), Some(Duration::from_secs(1)));
+            for key in ["a", "b", "c"] {
+                store.insert(key.to_string(), entry(key), 0).await.unwrap();
+            }
+            assert_eq!(store.remove("b", 0).await.unwrap(), Some("b".to_string()));
+
+            // nothing reaches L2 before the flush, but evicted entries are still readable
+            assert_eq!(store.l2.len(0).await.unwrap(), 0);
+            assert_eq!(store.pending(), 3);
+            assert_eq!(store.get("a", 0).await.unwrap(), Some("a".to_string()));
+            assert_eq!(store.get("b", 0).await.unwrap(), None);
+
+            // reads merge the buffer without flushing it
+            assert_eq!(store.len(0).await.unwrap(), 2);
+            let mut keys = store.keys(0).await.unwrap();
+            keys.sort();
+            assert_eq!(keys, vec!["a".to_string(), "c".to_string()]);
+            assert_eq!(store.entries(0).await.unwrap().len(), 2);
+            assert_eq!(store.pending(), 3);
+            assert_eq!(store.l2.len(0).await.unwrap(), 0);
+
+            assert_eq!(store.flush(0).await.unwrap(), 3);
+            assert_eq!(store.pending(), 0);
+            assert_eq!(store.l2.len(0).await.unwrap(), 2);
+
+            // a take removes the entry from every level
+            let taken = store.take("a", 0).await.unwrap();
+            assert_eq!(taken, Some(entry("a")));
+            assert_eq!(store.get("a", 0).await.unwrap(), None);
+            assert_eq!(store.keys(0).await.unwrap(), vec!["c".to_string()]);
+
+            // a buffered remove of a key in L2
+            assert!(store.remove("c", 0).await.unwrap().is_some());
+            assert_eq!(store.len(0).await.unwrap(), 0);
+            assert!(store.keys(0).await.unwrap().is_empty());
+        });
+    }
+}
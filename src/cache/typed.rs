@@ -0,0 +1,217 @@
+/// typed access to the cache; values are json encoded on the way in and decoded on the way out
+use crate::cache::supervisor::Supervisor;
+use crate::supervisor::SupervisorError;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum TypedError {
+    /// the value could not be serialized
+    Encode(serde_json::Error),
+    /// the stored json for the key could not be decoded into the requested type
+    Decode {
+        key: String,
+        source: serde_json::Error,
+    },
+    /// the request to the worker failed
+    Request(SupervisorError),
+}
+
+impl fmt::Display for TypedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypedError::Encode(e) => write!(f, "encode error: {}", e),
+            TypedError::Decode { key, source } => {
+                write!(f, "decode error for key {}: {}", key, source)
+            }
+            TypedError::Request(e) => write!(f, "request error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TypedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TypedError::Encode(e) => Some(e),
+            TypedError::Decode { source, .. } => Some(source),
+            TypedError::Request(e) => Some(e),
+        }
+    }
+}
+
+/// serialize the value to a json string
+pub(crate) fn encode<T: Serialize>(value: &T) -> Result<String, TypedError> {
+    serde_json::to_string(value).map_err(TypedError::Encode)
+}
+
+/// decode the optional json returned from the cache
+pub(crate) fn decode<T: DeserializeOwned>(
+    key: &str,
+    json: Option<String>,
+) -> Result<Option<T>, TypedError> {
+    match json {
+        Some(json) => match serde_json::from_str(&json) {
+            Ok(value) => Ok(Some(value)),
+            Err(source) => Err(TypedError::Decode {
+                key: key.to_string(),
+                source,
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+/// a cache of `T` values on top of a shared cache supervisor
+#[derive(Debug)]
+pub struct TypedCache<T> {
+    supervisor: Arc<Supervisor>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TypedCache<T> {
+    fn clone(&self) -> Self {
+        TypedCache {
+            supervisor: self.supervisor.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TypedCache<T> {
+    pub fn new(supervisor: Arc<Supervisor>) -> TypedCache<T> {
+        TypedCache {
+            supervisor,
+            _value: PhantomData,
+        }
+    }
+
+    /// return the underlying supervisor
+    pub fn supervisor(&self) -> &Supervisor {
+        &self.supervisor
+    }
+
+    /// store the value; return the previous value.  The new value is stored even if the
+    /// previous one fails to decode.
+    pub async fn set(&self, key: String, value: &T) -> Result<Option<T>, TypedError> {
+        self.supervisor.set_value(key, value).await
+    }
+
+    /// store the value; the entry expires after the ttl
+    pub async fn set_with_ttl(
+        &self,
+        key: String,
+        value: &T,
+        ttl: Duration,
+    ) -> Result<Option<T>, TypedError> {
+        let json = encode(value)?;
+        let prev = self
+            .supervisor
+            .set_with_ttl(key.to_string(), json, ttl)
+            .await
+            .map_err(TypedError::Request)?;
+
+        decode(&key, prev)
+    }
+
+    /// return the value for the key
+    pub async fn get(&self, key: String) -> Result<Option<T>, TypedError> {
+        self.supervisor.get_value(key).await
+    }
+
+    /// remove the value; return it if it existed
+    pub async fn remove(&self, key: String) -> Result<Option<T>, TypedError> {
+        let prev = self
+            .supervisor
+            .remove(key.to_string())
+            .await
+            .map_err(TypedError::Request)?;
+
+        decode(&key, prev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct Session {
+        user: String,
+        visits: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct Other {
+        count: Vec<u8>,
+    }
+
+    #[test]
+    fn typed_cache() {
+        async_std::task::block_on(async move {
+            let supervisor = Supervisor::new(2)
+                .await
+                .expect("should create the supervisor");
+            let cache: TypedCache<Session> = TypedCache::new(Arc::new(supervisor));
+
+            let key = "session-1".to_string();
+            let mut session = Session {
+                user: "dpw".to_string(),
+                visits: 1,
+            };
+
+            assert_eq!(cache.set(key.to_string(), &session).await.unwrap(), None);
+            assert_eq!(
+                cache.get(key.to_string()).await.unwrap(),
+                Some(session.clone())
+            );
+
+            session.visits += 1;
+            let prev = cache.set(key.to_string(), &session).await.unwrap();
+            assert_eq!(prev.map(|s| s.visits), Some(1));
+
+            let ttl = Duration::from_secs(60);
+            let prev = cache.set_with_ttl(key.to_string(), &session, ttl).await;
+            assert_eq!(prev.unwrap(), Some(session.clone()));
+
+            assert_eq!(cache.remove(key.to_string()).await.unwrap(), Some(session));
+            assert_eq!(cache.get(key.to_string()).await.unwrap(), None);
+
+            assert!(cache.supervisor().shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn decode_errors() {
+        async_std::task::block_on(async move {
+            let supervisor = Supervisor::new(1)
+                .await
+                .expect("should create the supervisor");
+
+            let key = "raw".to_string();
+            let r = supervisor
+                .set(key.to_string(), "not json".to_string())
+                .await;
+            assert!(r.is_ok());
+
+            match supervisor.get_value::<Session>(key.to_string()).await {
+                Err(TypedError::Decode { key: k, .. }) => assert_eq!(k, key),
+                other => panic!("should be a decode error: {:?}", other),
+            }
+
+            // a value of another type does not decode either
+            let other = Other { count: vec![1, 2] };
+            let r = supervisor.set_value("other".to_string(), &other).await;
+            assert!(r.unwrap().is_none());
+            let r = supervisor.get_value::<Session>("other".to_string()).await;
+            assert!(matches!(r, Err(TypedError::Decode { .. })));
+            let r = supervisor.get_value::<Other>("other".to_string()).await;
+            assert_eq!(r.unwrap(), Some(other));
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+}
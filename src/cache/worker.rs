@@ -1,82 +1,616 @@
 use anyhow::Result;
 use async_channel::bounded;
 use async_channel::Sender;
+use async_std::future;
 use domain_keys::keys::RouteKey;
 use log::*;
 // use serde::{Deserialize, Serialize};
 use async_channel::Receiver;
-use hashbrown::HashMap;
 use service_uptime::Uptime;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::worker::{JsonString, WorkerState, WorkerStatus, OK};
+use crate::cache::aof::{Aof, AofConfig, Record};
+use crate::cache::backend::{BackendConfig, CacheBackend};
+use crate::cache::clock::{self, Clock, SystemClock};
+use crate::cache::events::{CacheEvent, EventBus};
+use crate::cache::eviction::EvictionPolicy;
+use crate::cache::pattern::KeyPattern;
+use crate::cache::store::{Entry, MemoryStore, Removal};
+use crate::cache::tiered::TierConfig;
+use crate::supervisor::REQUEST_CHANNEL_SIZE;
+use crate::worker::{
+    HandlerFuture, Handoff, JsonString, WorkerState, WorkerStatus, WorkerTrait, OK,
+};
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Set(String, String, Sender<Option<String>>),
+    SetWithTtl(String, String, Duration, Sender<Option<String>>),
     Get(String, Sender<Option<String>>),
     Remove(String, Sender<Option<String>>),
+    SetNx(String, String, Sender<bool>), // store only if the key is missing; true if stored
+    SetXx(String, String, Sender<Option<String>>), // replace only if the key exists
+    CompareAndSwap(String, String, String, Sender<bool>), // key, expected, new; true if swapped
+    IncrBy(String, i64, Sender<Result<i64, String>>), // the new value or why it failed
+    Append(String, String, Sender<usize>), // the length of the new value
+    MGet(Vec<String>, Sender<Vec<Option<String>>>), // values in the order of the keys
+    MSet(Vec<(String, String)>, Sender<Vec<Option<String>>>), // the previous values
+    MRemove(Vec<String>, Sender<Vec<Option<String>>>), // the removed values
+    Ttl(String, Sender<KeyTtl>),         // the time to live for the key
+    Persist(String, Sender<bool>),       // remove the key's expiry; true if there was one
     Keys(Sender<Vec<String>>),
+    Scan(KeyPattern, Option<String>, usize, Sender<Vec<String>>), // a sorted page of matching keys
     Len(Sender<usize>),
-    Status(Sender<JsonString>), // request the worker's status
+    Take(Vec<String>, Sender<Handoff>), // remove the entries and hand them to another worker
+    Put(Vec<(String, Entry)>, Sender<usize>), // store entries from another worker
+    Dump(Sender<Vec<(String, Entry)>>), // a copy of all live entries for a snapshot
+    Export(Sender<Handoff>),            // a copy of all entries for the worker's replacement
+    Adopt(Vec<(String, Entry)>, Sender<usize>), // take over a recycled worker's entries and log
+    Status(Sender<JsonString>),         // request the worker's status
     Shutdown,
 }
 
+/// the response to a `Ttl` request
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeyTtl {
+    /// the key does not exist or has expired
+    #[default]
+    Missing,
+    /// the key exists and does not expire
+    Persistent,
+    /// the key expires after the remaining duration
+    Expires(Duration),
+}
+
+/// the cache worker options.  The limits apply to each worker; use `per_worker` to split a pool
+/// wide limit across the workers.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// the time source used to expire entries
+    pub clock: Arc<dyn Clock>,
+    /// how often the handler removes expired entries
+    pub sweep_interval: Duration,
+    /// the maximum number of entries; unlimited if None
+    pub max_entries: Option<usize>,
+    /// the maximum total size of keys and values in bytes; unlimited if None
+    pub max_bytes: Option<usize>,
+    /// decides which entries to evict when a limit is reached
+    pub eviction: EvictionPolicy,
+    /// where the entries are stored
+    pub backend: BackendConfig,
+    /// a second level behind the backend for a tiered cache; single level if None
+    pub l2: Option<TierConfig>,
+    /// log every write to an append-only file and replay it on start; memory only if None
+    pub aof: Option<AofConfig>,
+    /// the worker's route in the pool; set by the supervisor and used to name the log file
+    pub route: usize,
+    /// replay the route's log on start; false for a worker that replaces a recycled worker, which
+    /// starts a fresh log when it adopts the old worker's entries
+    pub replay: bool,
+    /// the keyspace change subscribers; shared by all workers in the pool
+    pub events: EventBus,
+    /// the worker marks itself broken once its error count reaches the threshold; never if None
+    pub error_threshold: Option<u16>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            clock: Arc::new(SystemClock),
+            sweep_interval: Duration::from_secs(1),
+            max_entries: None,
+            max_bytes: None,
+            eviction: EvictionPolicy::default(),
+            backend: BackendConfig::default(),
+            l2: None,
+            aof: None,
+            route: 0,
+            replay: true,
+            events: EventBus::default(),
+            error_threshold: Some(DEFAULT_ERROR_THRESHOLD),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// divide pool wide limits evenly across the workers, rounding up
+    pub fn per_worker(self, pool_size: usize) -> CacheConfig {
+        // `usize::div_ceil` without the 1.73 msrv; no overflow for a limit near usize::MAX
+        let workers = pool_size.max(1);
+        let split = |limit: usize| limit / workers + usize::from(limit % workers != 0);
+        CacheConfig {
+            max_entries: self.max_entries.map(split),
+            max_bytes: self.max_bytes.map(split),
+            ..self
+        }
+    }
+}
+
+/// the default number of errors before a worker is broken
+pub const DEFAULT_ERROR_THRESHOLD: u16 = 100;
+
+/// how often the handler wakes to sync the append-only log and finish a rewrite
+const AOF_TICK: Duration = Duration::from_millis(100);
+
 // the handler loop
-pub async fn handler(id: String, rx: Receiver<Command>) -> Result<()> {
+pub async fn handler(id: String, config: CacheConfig, rx: Receiver<Command>) -> Result<()> {
     let uptime = Uptime::new();
     let mut state = WorkerState::Idle;
-    let mut error_count = 0;
+    let mut error_count: u16 = 0;
+    let mut command_count = 0;
+    let mut last_command_at = None;
+    let clock = config.clock.clone();
+    let events = config.events.clone();
+
+    let mut cache = BackendConfig::open(&config).await?;
+    let mut last_sweep = Instant::now();
+    let mut last_flush = Instant::now();
+
+    // replay the log before taking requests
+    let mut aof = match config.aof.as_ref() {
+        Some(aof_config) if config.replay => {
+            let (aof, records) = Aof::open(aof_config, config.route).await?;
+            let now = clock.now_millis();
+            let count = records.len();
+            // evictions are logged, so the records are applied without limits; an eviction on
+            // the way could pick a different key than the one logged
+            let mut replayed = MemoryStore::default();
+            for record in records {
+                record.apply(&mut replayed, now).await?;
+            }
+            for (key, entry) in replayed.entries(now) {
+                cache.insert(key, entry, now).await?;
+            }
+            cache.sweep(now).await?;
+            // the replay restores the keys; it is not news to the subscribers
+            cache.drain_removals();
+            info!(
+                "worker id: {}, replayed {} records from {:?}",
+                id,
+                count,
+                aof.path()
+            );
+            Some(aof)
+        }
+        _ => None,
+    };
+
+    // now read and respond to requests; wake up for the expiry sweep when idle
+    loop {
+        let mut wait = config.sweep_interval.saturating_sub(last_sweep.elapsed());
+        if aof.is_some() {
+            wait = wait.min(AOF_TICK);
+        }
+        if let Some(interval) = cache.flush_interval() {
+            wait = wait.min(interval.saturating_sub(last_flush.elapsed()));
+        }
+        let next = future::timeout(wait, rx.recv()).await;
+
+        if last_sweep.elapsed() >= config.sweep_interval {
+            let count = backend_value(cache.sweep(clock.now_millis()).await, &mut error_count);
+            if count > 0 {
+                info!("worker id: {}, expired {} entries", id, count);
+            }
+            let errors = drain_removals(&events, &mut aof, cache.as_mut()).await;
+            error_count = error_count.saturating_add(errors);
+            last_sweep = Instant::now();
+        }
 
-    // should replace this with redis at some point
-    let mut cache: HashMap<String, String> = HashMap::new();
+        // write-behind changes go to the next level on the flush interval
+        if let Some(interval) = cache.flush_interval() {
+            if last_flush.elapsed() >= interval {
+                let count = backend_value(cache.flush(clock.now_millis()).await, &mut error_count);
+                if count > 0 {
+                    info!("worker id: {}, flushed {} changes", id, count);
+                }
+                last_flush = Instant::now();
+            }
+        }
+
+        if let Some(aof) = aof.as_mut() {
+            if let Err(e) = aof.tick().await {
+                error_count = error_count.saturating_add(1);
+                error!("worker id: {}, aof error: {}", id, e);
+            }
+            if aof.needs_rewrite() {
+                match cache.entries(clock.now_millis()).await {
+                    Ok(entries) => aof.start_rewrite(entries),
+                    Err(e) => error!("worker id: {}, aof rewrite not started: {}", id, e),
+                }
+            }
+        }
 
-    // now read and respond to requests
-    while let Ok(cmd) = rx.recv().await {
+        let cmd = match next {
+            Ok(Ok(cmd)) => cmd,
+            Ok(Err(_)) => break,
+            Err(_) => continue,
+        };
+
+        let now = clock.now_millis();
         info!("recv cmd: {:?}", cmd);
+        if !matches!(cmd, Command::Status(_)) {
+            command_count += 1;
+            last_command_at = Some(now);
+        }
+        if state != WorkerState::Broken {
+            state = WorkerState::Busy;
+        }
+
         match cmd {
             Command::Set(key, value, tx) => {
                 info!("k: {}, v: {}", key, value);
-
-                if let Some(v) = cache.insert(key, value) {
-                    error_count += send_optional_response(Some(v), tx).await;
-                } else {
-                    error_count += send_optional_response(None, tx).await;
-                }
+                error_count = error_count.saturating_add(
+                    log_writes(&mut aof, || vec![Record::set(&key, &value, None)]).await,
+                );
+                let prev = cache.insert(key.clone(), Entry::new(value.clone(), None), now);
+                let prev = published(prev.await, &events, || CacheEvent::Set { key, value });
+                let prev = backend_value(prev, &mut error_count);
+                error_count = error_count.saturating_add(send_optional_response(prev, tx).await);
+            }
+            Command::SetWithTtl(key, value, ttl, tx) => {
+                info!("k: {}, v: {}, ttl: {:?}", key, value, ttl);
+                let expires_at = clock::expires_at(now, ttl);
+                error_count = error_count.saturating_add(
+                    log_writes(&mut aof, || {
+                        vec![Record::set(&key, &value, Some(expires_at))]
+                    })
+                    .await,
+                );
+                let entry = Entry::new(value.clone(), Some(expires_at));
+                let prev = cache.insert(key.clone(), entry, now).await;
+                let prev = published(prev, &events, || CacheEvent::Set { key, value });
+                let prev = backend_value(prev, &mut error_count);
+                error_count = error_count.saturating_add(send_optional_response(prev, tx).await);
             }
             Command::Get(key, tx) => {
                 info!("get key: {}", key);
-                error_count += match cache.get(&key) {
-                    Some(v) => send_optional_response(Some(v.to_string()), tx).await,
-                    None => send_optional_response(None, tx).await,
-                }
+                let value = backend_value(cache.get(&key, now).await, &mut error_count);
+                error_count = error_count.saturating_add(send_optional_response(value, tx).await);
             }
             Command::Remove(key, tx) => {
                 info!("remove key: {}", key);
-                if let Some(v) = cache.remove(&key) {
-                    error_count += send_optional_response(Some(v), tx).await;
-                } else {
-                    error_count += send_optional_response(None, tx).await;
+                let prev = backend_value(cache.remove(&key, now).await, &mut error_count);
+                if prev.is_some() {
+                    error_count = error_count
+                        .saturating_add(log_writes(&mut aof, || vec![Record::remove(&key)]).await);
+                    events.publish(|| CacheEvent::Remove { key });
+                }
+                error_count = error_count.saturating_add(send_optional_response(prev, tx).await);
+            }
+            Command::SetNx(key, value, tx) => {
+                let current = checked(
+                    read_entry(cache.as_mut(), &key, now).await,
+                    &mut error_count,
+                );
+                let stored = match current {
+                    Some(None) => {
+                        let entry = Entry::new(value, None);
+                        let errors =
+                            write_entry(cache.as_mut(), &mut aof, &events, key, entry, now).await;
+                        error_count = error_count.saturating_add(errors);
+                        true
+                    }
+                    _ => false,
+                };
+                if tx.send(stored).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning set_nx");
+                }
+            }
+            Command::SetXx(key, value, tx) => {
+                let current = checked(
+                    read_entry(cache.as_mut(), &key, now).await,
+                    &mut error_count,
+                );
+                let prev = match current {
+                    Some(Some(prev)) => {
+                        let entry = Entry::new(value, None);
+                        let errors =
+                            write_entry(cache.as_mut(), &mut aof, &events, key, entry, now).await;
+                        error_count = error_count.saturating_add(errors);
+                        Some(prev.value)
+                    }
+                    _ => None,
+                };
+                error_count = error_count.saturating_add(send_optional_response(prev, tx).await);
+            }
+            Command::CompareAndSwap(key, expected, value, tx) => {
+                // the swap keeps the key's expiry
+                let current = checked(
+                    read_entry(cache.as_mut(), &key, now).await,
+                    &mut error_count,
+                );
+                let swapped = match current {
+                    Some(Some(current)) if current.value == expected => {
+                        let entry = Entry::new(value, current.expires_at);
+                        let errors =
+                            write_entry(cache.as_mut(), &mut aof, &events, key, entry, now).await;
+                        error_count = error_count.saturating_add(errors);
+                        true
+                    }
+                    _ => false,
+                };
+                if tx.send(swapped).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning compare and swap");
+                }
+            }
+            Command::IncrBy(key, delta, tx) => {
+                let current = match read_entry(cache.as_mut(), &key, now).await {
+                    Ok(current) => Ok(current),
+                    Err(e) => {
+                        error_count = error_count.saturating_add(1);
+                        error!("cache backend error: {}", e);
+                        Err(format!("cache backend error: {}", e))
+                    }
+                };
+                let result = current.and_then(|current| {
+                    let number = incremented(current.as_ref(), delta)?;
+                    Ok((number, current.and_then(|e| e.expires_at)))
+                });
+                let result = match result {
+                    Ok((number, expires_at)) => {
+                        let entry = Entry::new(number.to_string(), expires_at);
+                        let errors =
+                            write_entry(cache.as_mut(), &mut aof, &events, key, entry, now).await;
+                        error_count = error_count.saturating_add(errors);
+                        Ok(number)
+                    }
+                    Err(message) => Err(message),
+                };
+                if tx.send(result).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning incr_by");
+                }
+            }
+            Command::Append(key, suffix, tx) => {
+                let current = checked(
+                    read_entry(cache.as_mut(), &key, now).await,
+                    &mut error_count,
+                );
+                let len = match current {
+                    Some(current) => {
+                        let (mut value, expires_at) =
+                            current.map_or((String::new(), None), |e| (e.value, e.expires_at));
+                        value.push_str(&suffix);
+                        let len = value.len();
+                        let entry = Entry::new(value, expires_at);
+                        let errors =
+                            write_entry(cache.as_mut(), &mut aof, &events, key, entry, now).await;
+                        error_count = error_count.saturating_add(errors);
+                        len
+                    }
+                    None => 0,
+                };
+                if tx.send(len).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning append");
+                }
+            }
+            Command::MGet(keys, tx) => {
+                info!("mget keys: {:?}", keys);
+                let mut values = Vec::with_capacity(keys.len());
+                for key in keys.iter() {
+                    values.push(backend_value(cache.get(key, now).await, &mut error_count));
+                }
+                error_count = error_count.saturating_add(send_batch_response(values, tx).await);
+            }
+            Command::MSet(items, tx) => {
+                info!("mset count: {}", items.len());
+                error_count = error_count.saturating_add(
+                    log_writes(&mut aof, || {
+                        items
+                            .iter()
+                            .map(|(key, value)| Record::set(key, value, None))
+                            .collect()
+                    })
+                    .await,
+                );
+                let mut prev = Vec::with_capacity(items.len());
+                for (key, value) in items {
+                    let r = cache.insert(key.clone(), Entry::new(value.clone(), None), now);
+                    let r = published(r.await, &events, || CacheEvent::Set { key, value });
+                    prev.push(backend_value(r, &mut error_count));
+                }
+                error_count = error_count.saturating_add(send_batch_response(prev, tx).await);
+            }
+            Command::MRemove(keys, tx) => {
+                info!("mremove keys: {:?}", keys);
+                let mut prev = Vec::with_capacity(keys.len());
+                for key in keys.iter() {
+                    prev.push(backend_value(
+                        cache.remove(key, now).await,
+                        &mut error_count,
+                    ));
+                }
+                error_count = error_count.saturating_add(
+                    log_writes(&mut aof, || {
+                        keys.iter()
+                            .zip(prev.iter())
+                            .filter(|(_, value)| value.is_some())
+                            .map(|(key, _)| Record::remove(key))
+                            .collect()
+                    })
+                    .await,
+                );
+                for (key, _) in keys
+                    .into_iter()
+                    .zip(prev.iter())
+                    .filter(|(_, v)| v.is_some())
+                {
+                    events.publish(|| CacheEvent::Remove { key });
+                }
+                error_count = error_count.saturating_add(send_batch_response(prev, tx).await);
+            }
+            Command::Ttl(key, tx) => {
+                let ttl = backend_value(cache.ttl(&key, now).await, &mut error_count);
+                if tx.send(ttl).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning ttl");
+                }
+            }
+            Command::Persist(key, tx) => {
+                let persisted = backend_value(cache.persist(&key, now).await, &mut error_count);
+                if persisted && aof.is_some() {
+                    let value = backend_value(cache.get(&key, now).await, &mut error_count);
+                    if let Some(value) = value {
+                        let record = Record::set(&key, &value, None);
+                        error_count =
+                            error_count.saturating_add(log_writes(&mut aof, || vec![record]).await);
+                    }
+                }
+                if tx.send(persisted).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning persist");
                 }
             }
             Command::Keys(tx) => {
-                let list: Vec<String> = cache.keys().map(|x| x.to_string()).collect();
+                let list = backend_value(cache.keys(now).await, &mut error_count);
                 if tx.send(list).await.is_err() {
-                    error_count += error_count;
+                    error_count = error_count.saturating_add(1);
                     error!("error returning keys");
                 }
             }
+            Command::Scan(pattern, after, count, tx) => {
+                let page = cache.scan(&pattern, after.as_deref(), count, now).await;
+                let keys = backend_value(page, &mut error_count);
+                if tx.send(keys).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning scan page");
+                }
+            }
+            Command::Take(keys, tx) => {
+                let mut entries: Vec<(String, Entry)> = vec![];
+                for key in keys {
+                    if let Some(entry) =
+                        backend_value(cache.take(&key, now).await, &mut error_count)
+                    {
+                        entries.push((key, entry));
+                    }
+                }
+                info!("worker id: {}, hand over {} entries", id, entries.len());
+                error_count = error_count.saturating_add(
+                    log_writes(&mut aof, || {
+                        entries.iter().map(|(key, _)| Record::remove(key)).collect()
+                    })
+                    .await,
+                );
+                if let Err(e) = tx.send(Handoff::new(entries)).await {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning entries, keep them");
+                    // the supervisor gave up on the handoff; the entries stay here
+                    let entries = e.into_inner().downcast::<Vec<(String, Entry)>>();
+                    for (key, entry) in entries.unwrap_or_default() {
+                        let record = Record::set(&key, &entry.value, entry.expires_at);
+                        error_count =
+                            error_count.saturating_add(log_writes(&mut aof, || vec![record]).await);
+                        backend_value(cache.insert(key, entry, now).await, &mut error_count);
+                    }
+                }
+            }
+            Command::Put(entries, tx) => {
+                let mut stored = vec![];
+                for (key, entry) in entries {
+                    let record = Record::set(&key, &entry.value, entry.expires_at);
+                    if backend_value(cache.put(key, entry, now).await, &mut error_count) {
+                        stored.push(record);
+                    }
+                }
+                let count = stored.len();
+                error_count = error_count.saturating_add(log_writes(&mut aof, || stored).await);
+                if tx.send(count).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning put count");
+                }
+            }
+            Command::Dump(tx) => {
+                let entries = backend_value(cache.entries(now).await, &mut error_count);
+                if tx.send(entries).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning entries");
+                }
+            }
+            Command::Export(tx) => {
+                // the replacement writes a fresh log, so this one stops compacting first; on an
+                // error the request is dropped and the worker stays in place
+                let handed_over = match aof.as_mut() {
+                    Some(aof) => aof.hand_over().await.map_err(|e| {
+                        error!("worker id: {}, log not handed over: {}", id, e);
+                    }),
+                    None => Ok(()),
+                };
+                if handed_over.is_err() {
+                    error_count = error_count.saturating_add(1);
+                }
+                let entries = checked(cache.entries(now).await, &mut error_count);
+                if let (Ok(()), Some(entries)) = (handed_over, entries) {
+                    info!("worker id: {}, export {} entries", id, entries.len());
+                    if tx.send(Handoff::new(entries)).await.is_err() {
+                        error_count = error_count.saturating_add(1);
+                        error!("error returning entries");
+                    }
+                }
+            }
+            Command::Adopt(entries, tx) => {
+                let mut count = 0;
+                for (key, entry) in entries {
+                    if backend_value(cache.put(key, entry, now).await, &mut error_count) {
+                        count += 1;
+                    }
+                }
+
+                // the log starts over from the adopted entries and replaces the old worker's
+                let logged = match config.aof.as_ref() {
+                    Some(aof_config) => {
+                        let fresh = match cache.entries(now).await {
+                            Ok(entries) => Aof::create(aof_config, config.route, entries).await,
+                            Err(e) => Err(e),
+                        };
+                        match fresh {
+                            Ok(fresh) => {
+                                aof = Some(fresh);
+                                true
+                            }
+                            Err(e) => {
+                                error_count = error_count.saturating_add(1);
+                                error!("worker id: {}, log not started: {}", id, e);
+                                false
+                            }
+                        }
+                    }
+                    None => true,
+                };
+
+                if logged && tx.send(count).await.is_err() {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning adopt count");
+                }
+            }
             Command::Len(tx) => {
-                let sz = cache.len();
+                // includes expired entries that have not yet been swept
+                let sz = backend_value(cache.len(now).await, &mut error_count);
                 let _r = tx.send(sz).await;
             }
             Command::Status(tx) => {
-                let status = WorkerStatus::new(
+                // the status request itself does not make the worker busy
+                let current = match state {
+                    WorkerState::Busy if rx.is_empty() => WorkerState::Idle,
+                    _ => state.clone(),
+                };
+                let mut status = WorkerStatus::new(
                     id.to_string(),
                     OK.to_string(),
-                    state.clone(),
+                    current,
                     uptime.to_string(),
                     error_count,
                 );
+                status.eviction_count = cache.evictions();
+                status.tiers = cache.stats();
+                status.queue_len = rx.len();
+                status.queue_capacity = rx.capacity();
+                status.command_count = command_count;
+                status.last_command_at = last_command_at;
 
                 let msg = match serde_json::to_string(&status) {
                     Ok(js) => js,
@@ -87,7 +621,7 @@ pub async fn handler(id: String, rx: Receiver<Command>) -> Result<()> {
 
                 info!("status response: {}", msg);
                 if tx.send(msg).await.is_err() {
-                    error_count += 1;
+                    error_count = error_count.saturating_add(1);
                     error!("error returning status to channel: {:?}", tx);
                 }
             }
@@ -97,9 +631,145 @@ pub async fn handler(id: String, rx: Receiver<Command>) -> Result<()> {
                 break;
             }
         }
+
+        // reads and writes may have expired or evicted other entries
+        let errors = drain_removals(&events, &mut aof, cache.as_mut()).await;
+        error_count = error_count.saturating_add(errors);
+
+        if state != WorkerState::Broken {
+            if config
+                .error_threshold
+                .map_or(false, |max| error_count >= max)
+            {
+                state = WorkerState::Broken;
+                warn!("worker id: {}, broken after {} errors", id, error_count);
+            } else if rx.is_empty() {
+                state = WorkerState::Idle;
+            }
+        }
     }
 
     // helper functions
+    fn backend_value<T: Default>(result: std::io::Result<T>, error_count: &mut u16) -> T {
+        result.unwrap_or_else(|e| {
+            *error_count = error_count.saturating_add(1);
+            error!("cache backend error: {}", e);
+            T::default()
+        })
+    }
+
+    /// like `backend_value`, but None on an error so the caller can skip the write
+    fn checked<T>(result: std::io::Result<T>, error_count: &mut u16) -> Option<T> {
+        result
+            .map_err(|e| {
+                *error_count = error_count.saturating_add(1);
+                error!("cache backend error: {}", e);
+            })
+            .ok()
+    }
+
+    /// the live entry with its expiry
+    async fn read_entry(
+        cache: &mut dyn CacheBackend,
+        key: &str,
+        now: u64,
+    ) -> std::io::Result<Option<Entry>> {
+        let Some(value) = cache.get(key, now).await? else {
+            return Ok(None);
+        };
+
+        let expires_at = match cache.ttl(key, now).await? {
+            KeyTtl::Expires(ttl) => Some(clock::expires_at(now, ttl)),
+            _ => None,
+        };
+        Ok(Some(Entry::new(value, expires_at)))
+    }
+
+    /// store, log and publish the entry; return the number of errors
+    async fn write_entry(
+        cache: &mut dyn CacheBackend,
+        aof: &mut Option<Aof>,
+        events: &EventBus,
+        key: String,
+        entry: Entry,
+        now: u64,
+    ) -> u16 {
+        let record = Record::set(&key, &entry.value, entry.expires_at);
+        let mut errors = log_writes(aof, || vec![record]).await;
+        let value = entry.value.to_string();
+        let prev = cache.insert(key.to_string(), entry, now).await;
+        backend_value(
+            published(prev, events, || CacheEvent::Set { key, value }),
+            &mut errors,
+        );
+        errors
+    }
+
+    /// the value after adding the delta; a missing key counts as zero
+    fn incremented(current: Option<&Entry>, delta: i64) -> Result<i64, String> {
+        let number = match current {
+            Some(entry) => entry
+                .value
+                .parse::<i64>()
+                .map_err(|_| format!("value is not an integer: {}", entry.value))?,
+            None => 0,
+        };
+
+        number
+            .checked_add(delta)
+            .ok_or_else(|| "increment or decrement would overflow".to_string())
+    }
+
+    /// publish the event if the write succeeded
+    fn published<T>(
+        result: std::io::Result<T>,
+        events: &EventBus,
+        event: impl FnOnce() -> CacheEvent,
+    ) -> std::io::Result<T> {
+        if result.is_ok() {
+            events.publish(event);
+        }
+        result
+    }
+
+    /// publish the expired and evicted keys and log the evictions, which a replay can not repeat;
+    /// return the number of errors
+    async fn drain_removals(
+        events: &EventBus,
+        aof: &mut Option<Aof>,
+        cache: &mut dyn CacheBackend,
+    ) -> u16 {
+        let removals = cache.drain_removals();
+        let evicted: Vec<Record> = removals
+            .iter()
+            .filter_map(|removal| match removal {
+                Removal::Evicted(key) => Some(Record::remove(key)),
+                Removal::Expired(_) => None,
+            })
+            .collect();
+        for removal in removals {
+            events.publish(|| removal.into());
+        }
+
+        if evicted.is_empty() {
+            return 0;
+        }
+        log_writes(aof, || evicted).await
+    }
+
+    async fn log_writes(aof: &mut Option<Aof>, records: impl FnOnce() -> Vec<Record>) -> u16 {
+        let Some(aof) = aof.as_mut() else {
+            return 0;
+        };
+
+        if let Err(e) = aof.append(&records()).await {
+            error!("error writing to the append-only log: {}", e);
+            1u16
+        } else {
+            0u16
+        }
+    }
+
     async fn send_optional_response(msg: Option<String>, tx: Sender<Option<String>>) -> u16 {
         if let Err(e) = tx.send(msg).await {
             error!("error sending message: {:?}", e);
@@ -108,10 +778,10 @@ pub async fn handler(id: String, rx: Receiver<Command>) -> Result<()> {
             0u16
         }
     }
-    // helper functions
-    async fn send_response(msg: String, tx: Sender<String>) -> u16 {
+
+    async fn send_batch_response(msg: Vec<Option<String>>, tx: Sender<Vec<Option<String>>>) -> u16 {
         if let Err(e) = tx.send(msg).await {
-            error!("error sending message: {:?}", e);
+            error!("error sending batch response: {:?}", e);
             1u16
         } else {
             0u16
@@ -120,6 +790,16 @@ pub async fn handler(id: String, rx: Receiver<Command>) -> Result<()> {
 
     rx.close();
 
+    if let Err(e) = cache.flush(clock.now_millis()).await {
+        error!("worker id: {}, buffered changes lost: {}", id, e);
+    }
+
+    // finish a rewrite in progress and get the last writes to disk
+    if let Some(aof) = aof.as_mut() {
+        aof.wait_rewrite().await?;
+        aof.sync().await?;
+    }
+
     Ok(())
 }
 
@@ -134,7 +814,11 @@ pub struct Worker {
 impl Worker {
     /// create and start a new worker.
     pub async fn new() -> Worker {
-        let uptime = Uptime::new();
+        Worker::with_config(CacheConfig::default()).await
+    }
+
+    /// create and start a new worker with the given options
+    pub async fn with_config(config: CacheConfig) -> Worker {
         let id = RouteKey::create();
 
         // this is for the worker struct
@@ -142,22 +826,18 @@ impl Worker {
 
         info!("starting up worker, id: {}", id);
 
-        let (request_tx, request_receiver) = bounded(250);
+        let (request_tx, request_receiver) = bounded(REQUEST_CHANNEL_SIZE);
 
         // run the handler loop as a background task
         async_std::task::spawn(async move {
-            match handler(id.clone(), request_receiver).await {
+            match handler(id.clone(), config, request_receiver).await {
                 Ok(()) => info!("worker handler exit for worker id: {}", id),
                 Err(e) => error!("worker exex with error: {:?}", e),
             }
         });
 
         // define here, before the async loop to ensure it does not get moved
-        let worker = Worker {
-            id: wid,
-            uptime,
-            request_tx,
-        };
+        let worker = Worker::create(wid, request_tx);
 
         info!("worker created: {:?}", &worker);
 
@@ -186,9 +866,90 @@ impl Worker {
     }
 }
 
+impl WorkerTrait for Worker {
+    type Command = Command;
+    type Config = CacheConfig;
+
+    fn create(id: String, request_tx: Sender<Command>) -> Worker {
+        Worker {
+            id,
+            uptime: Uptime::new(),
+            request_tx,
+        }
+    }
+
+    fn route_config(config: &CacheConfig, route: usize) -> CacheConfig {
+        CacheConfig {
+            route,
+            ..config.clone()
+        }
+    }
+
+    fn handler(id: String, config: CacheConfig, rx: Receiver<Command>) -> HandlerFuture {
+        Box::pin(handler(id, config, rx))
+    }
+
+    fn status_command(tx: Sender<JsonString>) -> Command {
+        Command::Status(tx)
+    }
+
+    fn shutdown_command() -> Command {
+        Command::Shutdown
+    }
+
+    fn id(&self) -> String {
+        Worker::id(self)
+    }
+
+    fn request_channel(&self) -> Sender<Command> {
+        Worker::request_channel(self)
+    }
+
+    fn uptime_seconds(&self) -> Option<u64> {
+        Some(self.get_update_seconds())
+    }
+
+    fn successor_config(config: &CacheConfig, route: usize) -> CacheConfig {
+        CacheConfig {
+            route,
+            replay: false,
+            ..config.clone()
+        }
+    }
+
+    fn export_command(tx: Sender<Handoff>) -> Option<Command> {
+        Some(Command::Export(tx))
+    }
+
+    fn adopt_command(entries: Handoff, tx: Sender<usize>) -> Option<Command> {
+        entries
+            .downcast::<Vec<(String, Entry)>>()
+            .map(|entries| Command::Adopt(entries, tx))
+    }
+
+    fn is_keyed() -> bool {
+        true
+    }
+
+    fn keys_command(tx: Sender<Vec<String>>) -> Option<Command> {
+        Some(Command::Keys(tx))
+    }
+
+    fn take_command(keys: Vec<String>, tx: Sender<Handoff>) -> Option<Command> {
+        Some(Command::Take(keys, tx))
+    }
+
+    fn put_command(entries: Handoff, tx: Sender<usize>) -> Option<Command> {
+        entries
+            .downcast::<Vec<(String, Entry)>>()
+            .map(|entries| Command::Put(entries, tx))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::clock::ManualClock;
 
     #[test]
     fn new() {
@@ -302,4 +1063,286 @@ mod tests {
             assert!(request_channel.send(Command::Shutdown).await.is_ok());
         });
     }
+
+    /// send the request and wait for the response
+    async fn send<R>(worker: &Worker, command: impl FnOnce(Sender<R>) -> Command) -> R {
+        let (responder, rx) = async_channel::bounded(10);
+        worker
+            .request_channel()
+            .send(command(responder))
+            .await
+            .expect("send should not fail");
+
+        rx.recv().await.expect("receive should not fail")
+    }
+
+    fn manual_config(clock: &ManualClock, sweep_interval: Duration) -> CacheConfig {
+        CacheConfig {
+            clock: Arc::new(clock.clone()),
+            sweep_interval,
+            ..CacheConfig::default()
+        }
+    }
+
+    #[test]
+    fn ttl_persist() {
+        async_std::task::block_on(async move {
+            let clock = ManualClock::new(10_000);
+            let worker =
+                Worker::with_config(manual_config(&clock, Duration::from_secs(3600))).await;
+            let key = "session".to_string();
+            let ttl = Duration::from_secs(10);
+
+            let r = send(&worker, |tx| {
+                Command::SetWithTtl(key.clone(), "v1".to_string(), ttl, tx)
+            })
+            .await;
+            assert_eq!(r, None);
+            assert_eq!(
+                send(&worker, |tx| Command::Ttl(key.clone(), tx)).await,
+                KeyTtl::Expires(ttl)
+            );
+
+            clock.advance(Duration::from_secs(4));
+            assert_eq!(
+                send(&worker, |tx| Command::Ttl(key.clone(), tx)).await,
+                KeyTtl::Expires(Duration::from_secs(6))
+            );
+
+            // persist removes the expiry; a second persist has nothing to remove
+            assert!(send(&worker, |tx| Command::Persist(key.clone(), tx)).await);
+            assert!(!send(&worker, |tx| Command::Persist(key.clone(), tx)).await);
+            assert_eq!(
+                send(&worker, |tx| Command::Ttl(key.clone(), tx)).await,
+                KeyTtl::Persistent
+            );
+
+            clock.advance(Duration::from_secs(60));
+            let v = send(&worker, |tx| Command::Get(key.clone(), tx)).await;
+            assert_eq!(v, Some("v1".to_string()));
+
+            // a plain set clears the expiry as well
+            let r = send(&worker, |tx| {
+                Command::SetWithTtl(key.clone(), "v2".to_string(), ttl, tx)
+            })
+            .await;
+            assert_eq!(r, Some("v1".to_string()));
+            let r = send(&worker, |tx| {
+                Command::Set(key.clone(), "v3".to_string(), tx)
+            })
+            .await;
+            assert_eq!(r, Some("v2".to_string()));
+            assert_eq!(
+                send(&worker, |tx| Command::Ttl(key.clone(), tx)).await,
+                KeyTtl::Persistent
+            );
+
+            assert_eq!(
+                send(&worker, |tx| Command::Ttl("nope".to_string(), tx)).await,
+                KeyTtl::Missing
+            );
+
+            assert!(worker
+                .request_channel()
+                .send(Command::Shutdown)
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn lazy_expiry() {
+        async_std::task::block_on(async move {
+            let clock = ManualClock::new(10_000);
+            let worker =
+                Worker::with_config(manual_config(&clock, Duration::from_secs(3600))).await;
+            let key = "short".to_string();
+
+            send(&worker, |tx| {
+                Command::SetWithTtl(key.clone(), "v".to_string(), Duration::from_millis(500), tx)
+            })
+            .await;
+            clock.advance(Duration::from_millis(500));
+
+            // still stored, but invisible to keys and get; get removes it
+            assert_eq!(send(&worker, Command::Len).await, 1);
+            assert!(send(&worker, Command::Keys).await.is_empty());
+            assert_eq!(
+                send(&worker, |tx| Command::Get(key.clone(), tx)).await,
+                None
+            );
+            assert_eq!(send(&worker, Command::Len).await, 0);
+
+            // an expired value is not returned as the previous value
+            send(&worker, |tx| {
+                Command::SetWithTtl(key.clone(), "v".to_string(), Duration::from_millis(500), tx)
+            })
+            .await;
+            clock.advance(Duration::from_secs(1));
+            let r = send(&worker, |tx| {
+                Command::Set(key.clone(), "new".to_string(), tx)
+            })
+            .await;
+            assert_eq!(r, None);
+
+            assert!(worker
+                .request_channel()
+                .send(Command::Shutdown)
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn sweep_expired() {
+        async_std::task::block_on(async move {
+            let clock = ManualClock::new(10_000);
+            let worker =
+                Worker::with_config(manual_config(&clock, Duration::from_millis(10))).await;
+
+            for n in 0..10 {
+                let key = format!("key-{}", n);
+                let ttl = Duration::from_secs(n + 1);
+                send(&worker, |tx| {
+                    Command::SetWithTtl(key, "v".to_string(), ttl, tx)
+                })
+                .await;
+            }
+            send(&worker, |tx| {
+                Command::Set("forever".to_string(), "v".to_string(), tx)
+            })
+            .await;
+            assert_eq!(send(&worker, Command::Len).await, 11);
+
+            // expire the first five then let the idle sweep run
+            clock.advance(Duration::from_secs(5));
+            async_std::task::sleep(Duration::from_millis(50)).await;
+            assert_eq!(send(&worker, Command::Len).await, 6);
+
+            clock.advance(Duration::from_secs(60));
+            async_std::task::sleep(Duration::from_millis(50)).await;
+            assert_eq!(send(&worker, Command::Len).await, 1);
+            assert_eq!(
+                send(&worker, Command::Keys).await,
+                vec!["forever".to_string()]
+            );
+
+            assert!(worker
+                .request_channel()
+                .send(Command::Shutdown)
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn bounded_eviction() {
+        async_std::task::block_on(async move {
+            let config = CacheConfig {
+                max_entries: Some(2),
+                eviction: EvictionPolicy::Lru,
+                ..CacheConfig::default()
+            };
+            let worker = Worker::with_config(config).await;
+
+            for key in ["a", "b", "c"] {
+                send(&worker, |tx| {
+                    Command::Set(key.to_string(), key.to_string(), tx)
+                })
+                .await;
+            }
+
+            assert_eq!(send(&worker, Command::Len).await, 2);
+            assert_eq!(
+                send(&worker, |tx| Command::Get("a".to_string(), tx)).await,
+                None
+            );
+
+            let json = send(&worker, Command::Status).await;
+            let status: WorkerStatus = serde_json::from_str(&json).unwrap();
+            assert_eq!(status.eviction_count, 1);
+
+            assert!(worker
+                .request_channel()
+                .send(Command::Shutdown)
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn states() {
+        async_std::task::block_on(async move {
+            let clock = ManualClock::new(10_000);
+            let config = CacheConfig {
+                error_threshold: Some(2),
+                ..manual_config(&clock, Duration::from_secs(3600))
+            };
+            let worker = Worker::with_config(config).await;
+
+            let status = |json: String| serde_json::from_str::<WorkerStatus>(&json).unwrap();
+            let ws = status(send(&worker, Command::Status).await);
+            assert_eq!(ws.state, WorkerState::Idle);
+            assert_eq!(ws.command_count, 0);
+            assert_eq!(ws.last_command_at, None);
+
+            send(&worker, |tx| {
+                Command::Set("k".to_string(), "v".to_string(), tx)
+            })
+            .await;
+            clock.advance(Duration::from_secs(1));
+            let ws = status(send(&worker, Command::Status).await);
+            assert_eq!(ws.state, WorkerState::Idle);
+            assert_eq!(ws.command_count, 1);
+            assert_eq!(ws.last_command_at, Some(10_000));
+            assert_eq!(ws.queue_len, 0);
+
+            // responses nobody waits for are errors; the second one breaks the worker
+            for _ in 0..2 {
+                let (tx, rx) = async_channel::bounded(1);
+                drop(rx);
+                let request = worker.request_channel();
+                assert!(request.send(Command::Keys(tx)).await.is_ok());
+            }
+            let ws = status(send(&worker, Command::Status).await);
+            assert_eq!(ws.error_count, 2);
+            assert_eq!(ws.state, WorkerState::Broken);
+            assert_eq!(ws.command_count, 3);
+            assert_eq!(ws.last_command_at, Some(11_000));
+
+            // a broken worker still responds
+            let v = send(&worker, |tx| Command::Get("k".to_string(), tx)).await;
+            assert_eq!(v, Some("v".to_string()));
+
+            assert!(worker
+                .request_channel()
+                .send(Command::Shutdown)
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn per_worker_limits() {
+        let config = CacheConfig {
+            max_entries: Some(1_000),
+            max_bytes: Some(10),
+            ..CacheConfig::default()
+        }
+        .per_worker(3);
+
+        assert_eq!(config.max_entries, Some(334));
+        assert_eq!(config.max_bytes, Some(4));
+        assert_eq!(CacheConfig::default().per_worker(4).max_entries, None);
+
+        let unbounded = CacheConfig {
+            max_entries: Some(usize::MAX),
+            max_bytes: Some(0),
+            ..CacheConfig::default()
+        };
+        assert_eq!(unbounded.clone().per_worker(0).max_bytes, Some(0));
+        let config = unbounded.per_worker(2);
+        assert_eq!(config.max_entries, Some(usize::MAX / 2 + 1));
+        assert_eq!(config.max_bytes, Some(0));
+    }
 }
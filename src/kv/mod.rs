@@ -0,0 +1,4 @@
+/// concrete implementation of a typed key/value store, values are kept in memory as `T`
+///
+pub mod supervisor;
+pub mod worker;
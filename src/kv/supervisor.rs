@@ -0,0 +1,155 @@
+/// the key/value supervisor; a typed set, get, remove, keys and len API
+use crate::kv::worker::{Command, Value, Worker};
+use crate::supervisor::{PartialError, SupervisorError};
+use async_channel::Sender;
+
+/// the k/v supervisor is the generic supervisor over a pool of typed k/v workers
+pub type Supervisor<T> = crate::supervisor::Supervisor<Worker<T>>;
+
+impl<T: Value> Supervisor<T> {
+    /// store the value; return the previous value if it exists
+    pub async fn set(&self, key: String, value: T) -> Result<Option<T>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let resp = self
+            .request(route, |tx| Command::Set(key.to_string(), value, tx))
+            .await?;
+
+        // the old copy must not be migrated over the new value
+        let old = self.previous_owner(&key, Command::Remove).await?;
+        Ok(resp.or(old.flatten()))
+    }
+
+    /// return a clone of the value for the key
+    pub async fn get(&self, key: String) -> Result<Option<T>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let resp = self
+            .request(route, |tx| Command::Get(key.to_string(), tx))
+            .await?;
+
+        match resp {
+            Some(value) => Ok(Some(value)),
+            None => Ok(self.previous_owner(&key, Command::Get).await?.flatten()),
+        }
+    }
+
+    /// remove the item by key and return the value if it exists
+    pub async fn remove(&self, key: String) -> Result<Option<T>, SupervisorError> {
+        let _routing = self.routing_guard().await;
+        let route = self.get_route(&key)?;
+        let resp = self
+            .request(route, |tx| Command::Remove(key.to_string(), tx))
+            .await?;
+
+        let old = self.previous_owner(&key, Command::Remove).await?;
+        Ok(resp.or(old.flatten()))
+    }
+
+    /// while the pool is resized, send the command to the worker that owned the key before the
+    /// resize.  Return None if the owner is unchanged.
+    async fn previous_owner<R, C>(
+        &self,
+        key: &str,
+        command: C,
+    ) -> Result<Option<R>, SupervisorError>
+    where
+        C: FnOnce(String, Sender<R>) -> Command<T>,
+    {
+        match self.previous_route(key) {
+            Some(route) => {
+                let resp = self
+                    .request(route, |tx| command(key.to_string(), tx))
+                    .await?;
+                Ok(Some(resp))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// return the keys from all workers; on error holds the keys from the workers that responded
+    pub async fn keys(&self) -> Result<Vec<String>, PartialError<Vec<String>>> {
+        let results = self.request_all(Command::Keys).await;
+        PartialError::combine(results, vec![], |ks, list| ks.extend(list))
+    }
+
+    /// return the total number of entries from all workers
+    pub async fn len(&self) -> Result<usize, PartialError<usize>> {
+        let results = self.request_all(Command::Len).await;
+        PartialError::combine(results, 0, |sz, n| *sz += n)
+    }
+
+    /// return true if none of the workers hold any entries
+    pub async fn is_empty(&self) -> Result<bool, PartialError<usize>> {
+        Ok(self.len().await? == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain_keys::keys::RouteKey;
+    use std::sync::{Arc, Mutex};
+
+    /// a value that can not be serialized
+    #[derive(Debug, Clone)]
+    struct Counter {
+        name: String,
+        count: Arc<Mutex<u32>>,
+    }
+
+    #[test]
+    fn typed_values() {
+        async_std::task::block_on(async move {
+            let pool_size = 4;
+            let supervisor: Supervisor<Counter> = Supervisor::new(pool_size)
+                .await
+                .expect("should create the supervisor");
+            assert!(supervisor.is_empty().await.unwrap());
+
+            let mut keys = vec![];
+            for n in 0..20 {
+                let key = RouteKey::create();
+                let counter = Counter {
+                    name: format!("counter-{}", n),
+                    count: Arc::new(Mutex::new(n)),
+                };
+                assert!(supervisor
+                    .set(key.to_string(), counter)
+                    .await
+                    .unwrap()
+                    .is_none());
+                keys.push(key);
+            }
+
+            assert_eq!(supervisor.len().await.unwrap(), 20);
+            assert_eq!(supervisor.keys().await.unwrap().len(), 20);
+
+            // the clone shares the mutex with the stored value
+            let counter = supervisor.get(keys[5].to_string()).await.unwrap().unwrap();
+            assert_eq!(counter.name, "counter-5");
+            *counter.count.lock().unwrap() += 10;
+            let counter = supervisor.get(keys[5].to_string()).await.unwrap().unwrap();
+            assert_eq!(*counter.count.lock().unwrap(), 15);
+
+            let removed = supervisor.remove(keys[5].to_string()).await.unwrap();
+            assert_eq!(removed.unwrap().name, "counter-5");
+            assert!(supervisor.get(keys[5].to_string()).await.unwrap().is_none());
+            assert_eq!(supervisor.len().await.unwrap(), 19);
+
+            let status = supervisor.status().await;
+            assert_eq!(status.len(), pool_size);
+
+            // the values, including the shared mutex, move with a resize
+            assert!(supervisor.resize(pool_size * 2).await.is_ok());
+            assert_eq!(supervisor.len().await.unwrap(), 19);
+            let counter = supervisor.get(keys[6].to_string()).await.unwrap().unwrap();
+            assert_eq!(counter.name, "counter-6");
+            for key in keys.iter().filter(|k| **k != keys[5]) {
+                assert!(supervisor.get(key.to_string()).await.unwrap().is_some());
+            }
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+}
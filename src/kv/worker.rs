@@ -0,0 +1,205 @@
+use anyhow::Result;
+use async_channel::{Receiver, Sender};
+use hashbrown::HashMap;
+use log::*;
+use service_uptime::Uptime;
+use std::fmt::Debug;
+
+use crate::worker::{
+    HandlerFuture, Handoff, JsonString, WorkerState, WorkerStatus, WorkerTrait, OK,
+};
+
+/// the bounds for values held in the store
+pub trait Value: Clone + Debug + Send + Sync + 'static {}
+
+impl<T: Clone + Debug + Send + Sync + 'static> Value for T {}
+
+#[derive(Debug, Clone)]
+pub enum Command<T> {
+    Set(String, T, Sender<Option<T>>),
+    Get(String, Sender<Option<T>>),
+    Remove(String, Sender<Option<T>>),
+    Keys(Sender<Vec<String>>),
+    Len(Sender<usize>),
+    Take(Vec<String>, Sender<Handoff>), // remove the entries and hand them to another worker
+    Put(Vec<(String, T)>, Sender<usize>), // store entries from another worker
+    Export(Sender<Handoff>),            // a copy of all entries for the worker's replacement
+    Status(Sender<JsonString>),         // request the worker's status
+    Shutdown,
+}
+
+// the handler loop
+pub async fn handler<T: Value>(id: String, rx: Receiver<Command<T>>) -> Result<()> {
+    let uptime = Uptime::new();
+    let mut error_count: u16 = 0;
+    let mut store: HashMap<String, T> = HashMap::new();
+
+    while let Ok(cmd) = rx.recv().await {
+        match cmd {
+            Command::Set(key, value, tx) => {
+                error_count =
+                    error_count.saturating_add(send_response(store.insert(key, value), tx).await);
+            }
+            Command::Get(key, tx) => {
+                error_count =
+                    error_count.saturating_add(send_response(store.get(&key).cloned(), tx).await);
+            }
+            Command::Remove(key, tx) => {
+                error_count =
+                    error_count.saturating_add(send_response(store.remove(&key), tx).await);
+            }
+            Command::Keys(tx) => {
+                let list: Vec<String> = store.keys().map(|x| x.to_string()).collect();
+                error_count = error_count.saturating_add(send_response(list, tx).await);
+            }
+            Command::Len(tx) => {
+                error_count = error_count.saturating_add(send_response(store.len(), tx).await);
+            }
+            Command::Take(keys, tx) => {
+                let entries: Vec<(String, T)> = keys
+                    .into_iter()
+                    .filter_map(|key| store.remove(&key).map(|value| (key, value)))
+                    .collect();
+                if let Err(e) = tx.send(Handoff::new(entries)).await {
+                    error_count = error_count.saturating_add(1);
+                    error!("error returning entries, keep them");
+                    // the supervisor gave up on the handoff; the entries stay here
+                    let entries = e.into_inner().downcast::<Vec<(String, T)>>();
+                    store.extend(entries.unwrap_or_default());
+                }
+            }
+            Command::Put(entries, tx) => {
+                let mut count = 0;
+                for (key, value) in entries {
+                    if let hashbrown::hash_map::Entry::Vacant(e) = store.entry(key) {
+                        e.insert(value);
+                        count += 1;
+                    }
+                }
+                error_count = error_count.saturating_add(send_response(count, tx).await);
+            }
+            Command::Export(tx) => {
+                let entries: Vec<(String, T)> = store
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                error_count =
+                    error_count.saturating_add(send_response(Handoff::new(entries), tx).await);
+            }
+            Command::Status(tx) => {
+                let status = WorkerStatus::new(
+                    id.to_string(),
+                    OK.to_string(),
+                    WorkerState::Idle,
+                    uptime.to_string(),
+                    error_count,
+                );
+
+                let msg = serde_json::to_string(&status)?;
+                error_count = error_count.saturating_add(send_response(msg, tx).await);
+            }
+            Command::Shutdown => {
+                info!("worker id: {}, state: {:?}", id, WorkerState::Shutdown);
+                break;
+            }
+        }
+    }
+
+    // helper functions
+    async fn send_response<R>(msg: R, tx: Sender<R>) -> u16 {
+        if tx.send(msg).await.is_err() {
+            error!("error sending response");
+            1u16
+        } else {
+            0u16
+        }
+    }
+
+    rx.close();
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Worker<T> {
+    id: String,
+    uptime: Uptime,
+    request_tx: Sender<Command<T>>,
+}
+
+impl<T: Value> Worker<T> {
+    /// return the worker's id
+    pub fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    /// return the number of seconds this worker has been alive
+    pub fn get_uptime(&self) -> String {
+        self.uptime.to_string()
+    }
+
+    /// the channel used to send command requests to the worker
+    pub fn request_channel(&self) -> Sender<Command<T>> {
+        self.request_tx.clone()
+    }
+}
+
+impl<T: Value> WorkerTrait for Worker<T> {
+    type Command = Command<T>;
+    type Config = ();
+
+    fn create(id: String, request_tx: Sender<Command<T>>) -> Worker<T> {
+        Worker {
+            id,
+            uptime: Uptime::new(),
+            request_tx,
+        }
+    }
+
+    fn handler(id: String, _config: (), rx: Receiver<Command<T>>) -> HandlerFuture {
+        Box::pin(handler(id, rx))
+    }
+
+    fn status_command(tx: Sender<JsonString>) -> Command<T> {
+        Command::Status(tx)
+    }
+
+    fn shutdown_command() -> Command<T> {
+        Command::Shutdown
+    }
+
+    fn id(&self) -> String {
+        Worker::id(self)
+    }
+
+    fn request_channel(&self) -> Sender<Command<T>> {
+        Worker::request_channel(self)
+    }
+
+    fn is_keyed() -> bool {
+        true
+    }
+
+    fn keys_command(tx: Sender<Vec<String>>) -> Option<Command<T>> {
+        Some(Command::Keys(tx))
+    }
+
+    fn take_command(keys: Vec<String>, tx: Sender<Handoff>) -> Option<Command<T>> {
+        Some(Command::Take(keys, tx))
+    }
+
+    fn put_command(entries: Handoff, tx: Sender<usize>) -> Option<Command<T>> {
+        entries
+            .downcast::<Vec<(String, T)>>()
+            .map(|entries| Command::Put(entries, tx))
+    }
+
+    fn export_command(tx: Sender<Handoff>) -> Option<Command<T>> {
+        Some(Command::Export(tx))
+    }
+
+    // the replacement starts empty, so storing the entries adopts them all
+    fn adopt_command(entries: Handoff, tx: Sender<usize>) -> Option<Command<T>> {
+        Self::put_command(entries, tx)
+    }
+}
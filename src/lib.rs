@@ -3,8 +3,14 @@
 /// traits and common structs/methods
 pub mod worker;
 
+/// the generic supervisor for a pool of workers
+pub mod supervisor;
+
 /// concrete implementation
 pub mod cache;
 
+/// typed in-memory key/value store
+pub mod kv;
+
 /// the current app version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
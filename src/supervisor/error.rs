@@ -0,0 +1,192 @@
+/// errors returned from supervisor requests, naming the route and worker that caused them
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupervisorError {
+    /// the restart intensity was exceeded and no workers are running
+    Failed,
+    /// the supervisor has been shut down
+    Shutdown,
+    /// the pool can not be resized to the number of workers
+    InvalidSize(usize),
+    /// there is no worker at the route
+    NoWorker(usize),
+    /// the worker's request channel is closed
+    ChannelDown { route: usize, worker_id: String },
+    /// the worker dropped the request without a response
+    NoResponse { route: usize, worker_id: String },
+    /// the worker's request queue is full and the backpressure policy does not wait
+    QueueFull {
+        route: usize,
+        worker_id: String,
+        capacity: usize,
+    },
+    /// the worker did not respond within the timeout
+    Timeout {
+        route: usize,
+        worker_id: String,
+        timeout: Duration,
+    },
+    /// the key could not be mapped to a route
+    Route { key: String, message: String },
+    /// the key's value does not support the operation, e.g., an increment of a value that is not
+    /// an integer
+    Value { key: String, message: String },
+    /// the worker's response could not be decoded
+    Decode {
+        route: usize,
+        worker_id: String,
+        message: String,
+    },
+}
+
+impl SupervisorError {
+    /// the route of the worker that caused the error
+    pub fn route(&self) -> Option<usize> {
+        match self {
+            SupervisorError::Failed
+            | SupervisorError::Shutdown
+            | SupervisorError::InvalidSize(_)
+            | SupervisorError::Route { .. }
+            | SupervisorError::Value { .. } => None,
+            SupervisorError::NoWorker(route) => Some(*route),
+            SupervisorError::ChannelDown { route, .. }
+            | SupervisorError::NoResponse { route, .. }
+            | SupervisorError::QueueFull { route, .. }
+            | SupervisorError::Timeout { route, .. }
+            | SupervisorError::Decode { route, .. } => Some(*route),
+        }
+    }
+
+    /// return true for a timeout
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, SupervisorError::Timeout { .. })
+    }
+}
+
+impl fmt::Display for SupervisorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SupervisorError::Failed => write!(f, "supervisor has failed, no workers are running"),
+            SupervisorError::Shutdown => write!(f, "supervisor has been shut down"),
+            SupervisorError::InvalidSize(size) => write!(f, "invalid pool size: {}", size),
+            SupervisorError::NoWorker(route) => write!(f, "no worker at route {}", route),
+            SupervisorError::ChannelDown { worker_id, .. } => {
+                write!(f, "worker id {} request channel is down", worker_id)
+            }
+            SupervisorError::NoResponse { worker_id, .. } => {
+                write!(f, "worker id {} dropped the request", worker_id)
+            }
+            SupervisorError::QueueFull {
+                worker_id,
+                capacity,
+                ..
+            } => write!(
+                f,
+                "worker id {} queue is full at {} requests",
+                worker_id, capacity
+            ),
+            SupervisorError::Timeout {
+                worker_id, timeout, ..
+            } => write!(f, "worker id {} timed out after {:?}", worker_id, timeout),
+            SupervisorError::Route { key, message } => {
+                write!(f, "can not route key {}: {}", key, message)
+            }
+            SupervisorError::Value { key, message } => {
+                write!(f, "invalid value for key {}: {}", key, message)
+            }
+            SupervisorError::Decode {
+                worker_id, message, ..
+            } => write!(
+                f,
+                "worker id {} response decode error: {}",
+                worker_id, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SupervisorError {}
+
+/// returned when a request sent to every worker fails on some of them.  Holds the result combined
+/// from the workers that responded and an error for each worker that did not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialError<T> {
+    pub partial: T,
+    pub errors: Vec<SupervisorError>,
+}
+
+impl<T> PartialError<T> {
+    /// fold the per-worker results into a single value; any error makes the result partial
+    pub fn combine<R, F>(
+        results: Vec<Result<R, SupervisorError>>,
+        init: T,
+        mut fold: F,
+    ) -> Result<T, PartialError<T>>
+    where
+        F: FnMut(&mut T, R),
+    {
+        let mut partial = init;
+        let mut errors = vec![];
+        for result in results {
+            match result {
+                Ok(resp) => fold(&mut partial, resp),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(partial)
+        } else {
+            Err(PartialError { partial, errors })
+        }
+    }
+
+    /// the routes of the workers that failed
+    pub fn failed_routes(&self) -> Vec<usize> {
+        self.errors.iter().filter_map(|e| e.route()).collect()
+    }
+}
+
+impl<T> fmt::Display for PartialError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} worker(s) failed", self.errors.len())?;
+        for e in self.errors.iter() {
+            write!(f, "; {}", e)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for PartialError<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combine() {
+        let results: Vec<Result<usize, SupervisorError>> = vec![Ok(2), Ok(3)];
+        assert_eq!(PartialError::combine(results, 0, |sz, n| *sz += n), Ok(5));
+
+        let down = SupervisorError::ChannelDown {
+            route: 1,
+            worker_id: "w1".to_string(),
+        };
+        let results = vec![
+            Ok(2),
+            Err(down.clone()),
+            Ok(3),
+            Err(SupervisorError::Failed),
+        ];
+        let err = PartialError::combine(results, 0, |sz, n| *sz += n).unwrap_err();
+        assert_eq!(err.partial, 5);
+        assert_eq!(err.errors, vec![down, SupervisorError::Failed]);
+        assert_eq!(err.failed_routes(), vec![1]);
+        assert!(err
+            .to_string()
+            .starts_with("2 worker(s) failed; worker id w1"));
+    }
+}
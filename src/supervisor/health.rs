@@ -0,0 +1,239 @@
+/// heartbeat health monitoring; replaces workers that stop answering and recycles worn out ones
+use super::{RecycleConfig, Shared, Supervisor, WorkerExit};
+use crate::worker::{WorkerState, WorkerStatus, WorkerTrait};
+use async_channel::{bounded, Receiver, Sender, TrySendError};
+use async_std::future;
+use async_std::task;
+use futures::future::join_all;
+use log::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// the number of health events buffered for a subscriber; later events are dropped until the
+/// subscriber catches up
+pub const HEALTH_CHANNEL_SIZE: usize = 100;
+
+/// heartbeat options
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    /// the time between heartbeats
+    pub interval: Duration,
+    /// the time a worker has to answer a heartbeat
+    pub deadline: Duration,
+    /// the number of missed heartbeats in a row before the worker is replaced
+    pub max_missed: u16,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            interval: Duration::from_secs(1),
+            deadline: Duration::from_millis(500),
+            max_missed: 3,
+        }
+    }
+}
+
+/// a change in a worker's health
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthEvent {
+    /// the worker missed a heartbeat; `missed` counts the misses in a row
+    Unresponsive {
+        route: usize,
+        worker_id: String,
+        missed: u16,
+    },
+    /// the worker answered again after missing heartbeats
+    Recovered { route: usize, worker_id: String },
+    /// the worker was flagged as broken
+    Broken {
+        route: usize,
+        worker_id: String,
+        reason: String,
+    },
+    /// a new worker took over the route
+    Replaced {
+        route: usize,
+        worker_id: String,
+        new_worker_id: String,
+    },
+    /// a new worker took over the route and the old worker's entries after the old worker
+    /// passed a recycle limit
+    Recycled {
+        route: usize,
+        worker_id: String,
+        new_worker_id: String,
+        reason: String,
+    },
+}
+
+impl HealthEvent {
+    /// the route of the worker the event is about
+    pub fn route(&self) -> usize {
+        match self {
+            HealthEvent::Unresponsive { route, .. }
+            | HealthEvent::Recovered { route, .. }
+            | HealthEvent::Broken { route, .. }
+            | HealthEvent::Replaced { route, .. }
+            | HealthEvent::Recycled { route, .. } => *route,
+        }
+    }
+}
+
+/// the health event subscribers
+#[derive(Debug, Default)]
+pub struct HealthBus {
+    subscribers: Mutex<Vec<Sender<HealthEvent>>>,
+}
+
+impl HealthBus {
+    pub fn subscribe(&self) -> Receiver<HealthEvent> {
+        let (tx, rx) = bounded(HEALTH_CHANNEL_SIZE);
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(tx);
+
+        rx
+    }
+
+    /// send the event to each subscriber without waiting; closed subscribers are removed
+    pub fn publish(&self, event: HealthEvent) {
+        info!("health event: {:?}", event);
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|tx| match tx.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                warn!("health subscriber is full, event dropped");
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        });
+    }
+}
+
+/// the result of one heartbeat
+enum Heartbeat {
+    Answered(Option<WorkerStatus>),
+    Missed,
+}
+
+/// send the status request and wait up to the deadline for the answer
+async fn ping<W: WorkerTrait>(worker: &W, deadline: Duration) -> Heartbeat {
+    let (tx, rx) = bounded(1);
+    let answer = future::timeout(deadline, async move {
+        worker
+            .request_channel()
+            .send(W::status_command(tx))
+            .await
+            .ok()?;
+        rx.recv().await.ok()
+    });
+
+    match answer.await {
+        Ok(Some(json)) => Heartbeat::Answered(serde_json::from_str(&json).ok()),
+        _ => Heartbeat::Missed,
+    }
+}
+
+/// the monitor loop; runs until the supervisor shuts down or fails
+pub(super) async fn monitor<W: WorkerTrait>(
+    shared: Arc<Shared<W>>,
+    config: HealthConfig,
+    recycle: RecycleConfig,
+    exit_tx: Sender<WorkerExit>,
+) {
+    loop {
+        task::sleep(config.interval).await;
+        if exit_tx.is_closed() {
+            break;
+        }
+
+        let members = shared.members();
+        let beats = join_all(
+            members
+                .iter()
+                .map(|(_, _, worker)| ping(worker, config.deadline)),
+        )
+        .await;
+
+        for ((route, generation, worker), beat) in members.into_iter().zip(beats) {
+            let worker_id = worker.id();
+            match beat {
+                Heartbeat::Answered(status) => {
+                    if shared.record_heartbeat(route, generation) {
+                        shared.health.publish(HealthEvent::Recovered {
+                            route,
+                            worker_id: worker_id.to_string(),
+                        });
+                    }
+                    let Some(status) = status else {
+                        continue;
+                    };
+                    if status.state == WorkerState::Broken && shared.flag_broken(route, generation)
+                    {
+                        shared.health.publish(HealthEvent::Broken {
+                            route,
+                            worker_id,
+                            reason: "worker reported broken".to_string(),
+                        });
+                    }
+                    let Some(reason) = recycle.due(&worker, &status) else {
+                        continue;
+                    };
+                    // the handoff runs on its own so the heartbeats go on
+                    if shared.start_recycle(route, generation) {
+                        let shared = shared.clone();
+                        let exit_tx = exit_tx.clone();
+                        task::spawn(async move {
+                            shared.recycle(route, generation, reason, &exit_tx).await;
+                        });
+                    }
+                }
+                Heartbeat::Missed => {
+                    let Some(missed) = shared.record_missed(route, generation) else {
+                        continue;
+                    };
+                    shared.health.publish(HealthEvent::Unresponsive {
+                        route,
+                        worker_id: worker_id.to_string(),
+                        missed,
+                    });
+                    // a worker past the limit is already waiting to be replaced
+                    if missed != config.max_missed.max(1) {
+                        continue;
+                    }
+
+                    let reason = format!("missed {} heartbeats", missed);
+                    shared.flag_broken(route, generation);
+                    shared.health.publish(HealthEvent::Broken {
+                        route,
+                        worker_id: worker_id.to_string(),
+                        reason: reason.to_string(),
+                    });
+
+                    // replace it through the restart policy as if it had exited
+                    let exit = WorkerExit {
+                        route,
+                        generation,
+                        worker_id,
+                        outcome: Err(reason),
+                    };
+                    if exit_tx.send(exit).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    info!("health monitor exit");
+}
+
+impl<W: WorkerTrait> Supervisor<W> {
+    /// return a channel of the pool's health changes: missed heartbeats, recoveries, broken
+    /// workers and replacements
+    pub fn subscribe_health(&self) -> Receiver<HealthEvent> {
+        self.shared.health.subscribe()
+    }
+}
@@ -0,0 +1,1680 @@
+/// the generic supervisor that creates, routes requests to and shuts down a pool of workers
+use crate::worker::{WorkerState, WorkerStatus, WorkerTrait};
+use anyhow::{bail, Result};
+use async_channel::{bounded, unbounded, Receiver, Sender, TrySendError};
+use async_std::future;
+use async_std::task::{self, JoinHandle};
+use domain_keys::keys::RouteKey;
+use futures::future::join_all;
+use futures::FutureExt;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+pub mod error;
+mod health;
+mod recycle;
+mod resize;
+pub mod restart;
+pub mod routing;
+mod shutdown;
+
+pub use error::{PartialError, SupervisorError};
+pub use health::{HealthConfig, HealthEvent};
+pub use recycle::RecycleConfig;
+use restart::RestartIntensity;
+pub use restart::{RestartPolicy, RestartStrategy};
+pub use routing::{PoolView, RouteKeyRouter, Router};
+pub use shutdown::{ExitKind, ShutdownReport, WorkerExitReport};
+
+/// the default number of requests that may be queued for a single worker
+pub const REQUEST_CHANNEL_SIZE: usize = 250;
+
+/// what a request does when the worker's queue is full
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// wait for room in the queue, up to the request timeout; a queue that stays full returns
+    /// `SupervisorError::QueueFull` and is not counted as a timeout against the worker
+    #[default]
+    Block,
+    /// return `SupervisorError::QueueFull` at once
+    FailFast,
+    /// queue the request with the next worker that has room, or return `QueueFull` if every queue
+    /// is full.  Only for stateless workers; a keyed request would reach a worker that does not
+    /// own the key, so `Supervisor::with_config` rejects it for workers with a `keys_command`.
+    /// Requests meant for one worker, like `request_all` and the status requests, fail fast.
+    Shed,
+}
+
+/// the supervisor's lifecycle state
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SupervisorState {
+    #[default]
+    Running,
+    /// the restart intensity was exceeded; the workers have been stopped and are not restarted
+    Failed,
+    Shutdown,
+}
+
+/// supervisor options; use `SupervisorConfig::default()` and override as needed.  The `worker`
+/// config is handed to every worker the supervisor starts, including replacements.
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig<C = ()> {
+    pub restart: RestartPolicy,
+    /// the default time to wait for a worker's response
+    pub request_timeout: Duration,
+    /// the number of timeouts after which a worker is flagged as broken
+    pub max_timeouts: u16,
+    /// decides which worker handles each request
+    pub router: Arc<dyn Router>,
+    /// the number of requests that may be queued for each worker
+    pub queue_capacity: usize,
+    /// what a request does when the worker's queue is full
+    pub backpressure: Backpressure,
+    /// the heartbeat monitor's options; no monitor if None
+    pub health: Option<HealthConfig>,
+    /// the limits after which a healthy worker is replaced with a new one
+    pub recycle: RecycleConfig,
+    pub worker: C,
+}
+
+impl<C: Default> Default for SupervisorConfig<C> {
+    fn default() -> Self {
+        SupervisorConfig {
+            restart: RestartPolicy::default(),
+            request_timeout: Duration::from_secs(5),
+            max_timeouts: 3,
+            router: Arc::new(RouteKeyRouter::default()),
+            queue_capacity: REQUEST_CHANNEL_SIZE,
+            backpressure: Backpressure::default(),
+            health: Some(HealthConfig::default()),
+            recycle: RecycleConfig::default(),
+            worker: C::default(),
+        }
+    }
+}
+
+/// a route slot in the pool; holds the current worker and the task running its handler loop
+#[derive(Debug)]
+struct WorkerSlot<W: WorkerTrait> {
+    worker: W,
+    /// taken by a graceful shutdown to await the handler's exit
+    handle: Option<JoinHandle<Outcome>>,
+    generation: u64,
+    restart_count: u16,
+    timeouts: u16,
+    /// heartbeats missed in a row
+    missed: u16,
+    broken: bool,
+    /// held for reading while a request is queued and for writing while the worker is recycled;
+    /// kept by the new worker so requests waiting on a recycle go to it
+    gate: Arc<async_std::sync::RwLock<()>>,
+    /// set while a recycle of the worker is under way
+    recycling: bool,
+}
+
+impl<W: WorkerTrait> WorkerSlot<W> {
+    fn new(worker: W, handle: JoinHandle<Outcome>, generation: u64) -> WorkerSlot<W> {
+        WorkerSlot {
+            worker,
+            handle: Some(handle),
+            generation,
+            restart_count: 0,
+            timeouts: 0,
+            missed: 0,
+            broken: false,
+            gate: Arc::new(async_std::sync::RwLock::new(())),
+            recycling: false,
+        }
+    }
+}
+
+/// how a worker's handler loop ended; Err holds the error or panic
+type Outcome = Result<(), String>;
+
+/// sent by a worker's background task when the handler loop exits for any reason
+#[derive(Debug)]
+struct WorkerExit {
+    route: usize,
+    generation: u64,
+    worker_id: String,
+    outcome: Outcome,
+}
+
+/// the state shared between the supervisor and its supervise task
+#[derive(Debug)]
+struct Shared<W: WorkerTrait> {
+    slots: RwLock<Vec<WorkerSlot<W>>>,
+    state: RwLock<SupervisorState>,
+    worker_config: W::Config,
+    max_timeouts: u16,
+    /// the time to wait for each step of a recycle handoff
+    request_timeout: Duration,
+    /// the request queue size for new workers
+    queue_capacity: usize,
+    backpressure: Backpressure,
+    /// the next worker generation; unique across all routes so late exits are never mistaken
+    /// for the current worker
+    generation: AtomicU64,
+    /// the number of routes keys are mapped to
+    pool_size: AtomicUsize,
+    /// the pool size before a resize while entries are migrated
+    previous_size: RwLock<Option<usize>>,
+    /// held for reading by keyed requests and for writing while the routing changes or entries
+    /// move between workers
+    routing_lock: async_std::sync::RwLock<()>,
+    /// allows one resize at a time
+    resizing: async_std::sync::Mutex<()>,
+    health: health::HealthBus,
+    /// set by a graceful shutdown to turn new requests away
+    draining: AtomicBool,
+}
+
+impl<W: WorkerTrait> Shared<W> {
+    fn read_slots(&self) -> RwLockReadGuard<'_, Vec<WorkerSlot<W>>> {
+        self.slots.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_slots(&self) -> RwLockWriteGuard<'_, Vec<WorkerSlot<W>>> {
+        self.slots.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn state(&self) -> SupervisorState {
+        *self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// return true once a graceful shutdown has started
+    fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    fn set_state(&self, state: SupervisorState) {
+        *self.state.write().unwrap_or_else(|e| e.into_inner()) = state;
+    }
+
+    fn workers(&self) -> Vec<W> {
+        self.read_slots()
+            .iter()
+            .map(|slot| slot.worker.clone())
+            .collect()
+    }
+
+    /// return true if the generation is the current one for the route
+    fn is_current(&self, route: usize, generation: u64) -> bool {
+        self.read_slots()
+            .get(route)
+            .map_or(false, |slot| slot.generation == generation)
+    }
+
+    /// the route, generation and worker in each slot
+    fn members(&self) -> Vec<(usize, u64, W)> {
+        self.read_slots()
+            .iter()
+            .enumerate()
+            .map(|(route, slot)| (route, slot.generation, slot.worker.clone()))
+            .collect()
+    }
+
+    /// reset the missed heartbeats; return true if the worker had missed any
+    fn record_heartbeat(&self, route: usize, generation: u64) -> bool {
+        let mut slots = self.write_slots();
+        match slots.get_mut(route) {
+            Some(slot) if slot.generation == generation && slot.missed > 0 => {
+                slot.missed = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// count a missed heartbeat; return the misses in a row, or None if the worker was replaced
+    fn record_missed(&self, route: usize, generation: u64) -> Option<u16> {
+        let mut slots = self.write_slots();
+        let slot = slots
+            .get_mut(route)
+            .filter(|slot| slot.generation == generation)?;
+        slot.missed = slot.missed.saturating_add(1);
+
+        Some(slot.missed)
+    }
+
+    /// flag the worker as broken; return true if it was not already
+    fn flag_broken(&self, route: usize, generation: u64) -> bool {
+        let mut slots = self.write_slots();
+        match slots.get_mut(route) {
+            Some(slot) if slot.generation == generation && !slot.broken => {
+                warn!("worker id: {} flagged as broken", slot.worker.id());
+                slot.broken = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// count a timeout against the worker; flag it as broken once it reaches the limit
+    fn record_timeout(&self, route: usize, generation: u64) {
+        let mut slots = self.write_slots();
+        if let Some(slot) = slots.get_mut(route) {
+            if slot.generation == generation {
+                slot.timeouts = slot.timeouts.saturating_add(1);
+                if slot.timeouts >= self.max_timeouts && !slot.broken {
+                    warn!("worker id: {} flagged as broken", slot.worker.id());
+                    slot.broken = true;
+                }
+            }
+        }
+    }
+
+    /// replace the workers in the routes with new ones.  The old handler tasks are stopped first
+    /// so two handlers never own a route, e.g., a hung worker and its replacement writing the
+    /// same log file.
+    async fn replace(&self, routes: Range<usize>, exit_tx: &Sender<WorkerExit>) {
+        let mut stopping = vec![];
+        let mut handles = vec![];
+        for (route, slot) in self.write_slots().iter_mut().enumerate() {
+            if routes.contains(&route) {
+                stopping.push((route, slot.generation));
+                handles.extend(slot.handle.take());
+            }
+        }
+
+        // stops the siblings; the exited worker's task is already done
+        for handle in handles {
+            handle.cancel().await;
+        }
+
+        let mut slots = self.write_slots();
+        for (route, generation) in stopping {
+            // skip a slot that changed hands while the old task stopped
+            let Some(slot) = slots.get_mut(route).filter(|s| s.generation == generation) else {
+                continue;
+            };
+
+            let next = self.generation.fetch_add(1, Ordering::SeqCst);
+            let (worker, handle) = start_worker::<W>(route, next, self, exit_tx.clone(), false);
+
+            let restart_count = slot.restart_count + 1;
+            let prev = std::mem::replace(slot, WorkerSlot::new(worker, handle, next));
+            slot.restart_count = restart_count;
+            self.health.publish(HealthEvent::Replaced {
+                route,
+                worker_id: prev.worker.id(),
+                new_worker_id: slot.worker.id(),
+            });
+        }
+    }
+
+    /// start workers for the new routes up to `size`
+    fn grow(&self, size: usize, exit_tx: &Sender<WorkerExit>) {
+        let mut slots = self.write_slots();
+        for route in slots.len()..size {
+            let generation = self.generation.fetch_add(1, Ordering::SeqCst);
+            let (worker, handle) =
+                start_worker::<W>(route, generation, self, exit_tx.clone(), false);
+            slots.push(WorkerSlot::new(worker, handle, generation));
+        }
+    }
+}
+
+/// closes the exit channel when the last handle to the pool is dropped; this ends the supervise
+/// task which releases the workers
+#[derive(Debug)]
+struct ExitGuard(Sender<WorkerExit>);
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+#[derive(Debug)]
+pub struct Supervisor<W: WorkerTrait> {
+    shared: Arc<Shared<W>>,
+    exit_guard: Arc<ExitGuard>,
+    timeout: Duration,
+    router: Arc<dyn Router>,
+}
+
+impl<W: WorkerTrait> Supervisor<W> {
+    /// create and start the worker pool with the default configuration
+    pub async fn new(pool_size: usize) -> Result<Supervisor<W>> {
+        Self::with_config(pool_size, SupervisorConfig::default()).await
+    }
+
+    /// create and start the worker pool
+    pub async fn with_config(
+        pool_size: usize,
+        config: SupervisorConfig<W::Config>,
+    ) -> Result<Supervisor<W>> {
+        if config.backpressure == Backpressure::Shed && W::is_keyed() {
+            bail!("backpressure Shed needs stateless workers, keyed requests can not move");
+        }
+
+        let (exit_tx, exit_rx) = unbounded();
+
+        let shared = Arc::new(Shared {
+            slots: RwLock::new(vec![]),
+            state: RwLock::new(SupervisorState::Running),
+            worker_config: config.worker,
+            max_timeouts: config.max_timeouts,
+            request_timeout: config.request_timeout,
+            queue_capacity: config.queue_capacity.max(1),
+            backpressure: config.backpressure,
+            generation: AtomicU64::new(0),
+            pool_size: AtomicUsize::new(0),
+            previous_size: RwLock::new(None),
+            routing_lock: async_std::sync::RwLock::new(()),
+            resizing: async_std::sync::Mutex::new(()),
+            health: health::HealthBus::default(),
+            draining: AtomicBool::new(false),
+        });
+        shared.grow(pool_size, &exit_tx);
+        shared.pool_size.store(pool_size, Ordering::SeqCst);
+
+        task::spawn(supervise(
+            shared.clone(),
+            config.restart,
+            exit_tx.clone(),
+            exit_rx,
+        ));
+        if let Some(health) = config.health {
+            let monitor = health::monitor(shared.clone(), health, config.recycle, exit_tx.clone());
+            task::spawn(monitor);
+        }
+
+        Ok(Supervisor {
+            shared,
+            exit_guard: Arc::new(ExitGuard(exit_tx)),
+            timeout: config.request_timeout,
+            router: config.router,
+        })
+    }
+
+    /// return a handle to the same worker pool that waits up to `timeout` for each response,
+    /// e.g., `supervisor.with_timeout(Duration::from_millis(50)).get(key)`
+    pub fn with_timeout(&self, timeout: Duration) -> Supervisor<W> {
+        Supervisor {
+            shared: self.shared.clone(),
+            exit_guard: self.exit_guard.clone(),
+            timeout,
+            router: self.router.clone(),
+        }
+    }
+
+    /// the time this handle waits for a worker's response
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// shut the workers down
+    pub async fn shutdown(&self) -> Result<()> {
+        // stop listening for worker exits so the workers are not restarted
+        self.exit_guard.0.close();
+        if self.state() == SupervisorState::Running {
+            self.shared.set_state(SupervisorState::Shutdown);
+        }
+
+        for worker in self.workers().iter() {
+            info!("shut worker, id: {} down", worker.id());
+            let tx = worker.request_channel();
+            let r = tx.send(W::shutdown_command()).await;
+            info!("ok? {:?}", r);
+        }
+
+        Ok(())
+    }
+
+    /// return the supervisor's lifecycle state
+    pub fn state(&self) -> SupervisorState {
+        self.shared.state()
+    }
+
+    /// return a snapshot of the current workers in route order
+    pub fn workers(&self) -> Vec<W> {
+        self.shared.workers()
+    }
+
+    /// return the current worker at the given route
+    pub fn worker(&self, route: usize) -> Option<W> {
+        self.shared
+            .read_slots()
+            .get(route)
+            .map(|slot| slot.worker.clone())
+    }
+
+    /// return the number of times the worker at the given route has been replaced
+    pub fn restart_count(&self, route: usize) -> u16 {
+        self.shared
+            .read_slots()
+            .get(route)
+            .map_or(0, |slot| slot.restart_count)
+    }
+
+    /// return true if the worker at the route has been flagged as broken
+    pub fn is_broken(&self, route: usize) -> bool {
+        self.shared
+            .read_slots()
+            .get(route)
+            .map_or(false, |slot| slot.broken)
+    }
+
+    /// return the route number for the key from the configured router.  A key that can not be
+    /// routed returns `SupervisorError::Route`.
+    pub fn get_route(&self, key: &str) -> Result<usize, SupervisorError> {
+        self.route_for_size(key, self.pool_size())
+    }
+
+    /// return the route for the key in a pool of `size` workers
+    fn route_for_size(&self, key: &str, size: usize) -> Result<usize, SupervisorError> {
+        let queue_len = |route: usize| self.queue_len(route);
+        self.router.route(key, &PoolView::new(size, &queue_len))
+    }
+
+    /// the router used to map requests to workers
+    pub fn router(&self) -> Arc<dyn Router> {
+        self.router.clone()
+    }
+
+    /// the config handed to each new worker
+    pub fn worker_config(&self) -> &W::Config {
+        &self.shared.worker_config
+    }
+
+    /// the number of requests waiting in the worker's queue
+    pub fn queue_len(&self, route: usize) -> usize {
+        self.shared
+            .read_slots()
+            .get(route)
+            .map_or(0, |slot| slot.worker.request_channel().len())
+    }
+
+    /// send a command to the worker at the given route and wait for the response.  The command is
+    /// built from the responder channel, e.g., `supervisor.request(route, |tx| Command::Get(key, tx))`
+    pub async fn request<R, F>(&self, route: usize, command: F) -> Result<R, SupervisorError>
+    where
+        F: FnOnce(Sender<R>) -> W::Command,
+    {
+        self.request_with_timeout(route, self.timeout, command)
+            .await
+    }
+
+    /// send a command and wait up to `timeout` for the response.  A timeout is counted against the
+    /// worker and returns `SupervisorError::Timeout`.
+    pub async fn request_with_timeout<R, F>(
+        &self,
+        route: usize,
+        timeout: Duration,
+        command: F,
+    ) -> Result<R, SupervisorError>
+    where
+        F: FnOnce(Sender<R>) -> W::Command,
+    {
+        let (responder, rx) = bounded(1);
+        self.dispatch(route, timeout, command(responder), rx, true)
+            .await
+    }
+
+    /// send a command to exactly the worker at the route; it is never shed to another worker
+    async fn request_route<R, F>(&self, route: usize, command: F) -> Result<R, SupervisorError>
+    where
+        F: FnOnce(Sender<R>) -> W::Command,
+    {
+        let (responder, rx) = bounded(1);
+        self.dispatch(route, self.timeout, command(responder), rx, false)
+            .await
+    }
+
+    /// send the command to the worker and wait up to `timeout` for the response on `rx`.  Under
+    /// `Shed` the command may go to another worker only if `shed` is set, otherwise it fails fast.
+    async fn dispatch<R>(
+        &self,
+        route: usize,
+        timeout: Duration,
+        command: W::Command,
+        rx: Receiver<R>,
+        shed: bool,
+    ) -> Result<R, SupervisorError> {
+        if self.state() == SupervisorState::Failed {
+            return Err(SupervisorError::Failed);
+        }
+        if self.shared.is_draining() {
+            return Err(SupervisorError::Shutdown);
+        }
+
+        let gate = match self.shared.read_slots().get(route) {
+            Some(slot) => slot.gate.clone(),
+            None => return Err(SupervisorError::NoWorker(route)),
+        };
+
+        // a blocking send waits for room inside the timeout; the other policies queue at once
+        let started = Instant::now();
+        let queued = {
+            // wait out a recycle of the worker so the request reaches its replacement
+            let _gate = gate.read().await;
+            match self.shared.backpressure {
+                Backpressure::Block => self.wait_for_room(route, timeout, command).await,
+                _ => self.enqueue(route, command, shed),
+            }
+        };
+        let (route, worker, generation) = match queued {
+            Ok(queued) => queued,
+            Err(SupervisorError::ChannelDown { .. }) if self.shared.is_draining() => {
+                return Err(SupervisorError::Shutdown)
+            }
+            Err(e) => return Err(e),
+        };
+
+        // only the wait for the response counts against the worker
+        let remaining = timeout.saturating_sub(started.elapsed());
+        let err = match future::timeout(remaining, rx.recv()).await {
+            Ok(Ok(resp)) => return Ok(resp),
+            // a worker stopped by a graceful shutdown drops the requests queued behind it
+            Ok(Err(_)) if self.shared.is_draining() => SupervisorError::Shutdown,
+            Ok(Err(_)) => SupervisorError::NoResponse {
+                route,
+                worker_id: worker.id(),
+            },
+            Err(_) => {
+                self.shared.record_timeout(route, generation);
+                SupervisorError::Timeout {
+                    route,
+                    worker_id: worker.id(),
+                    timeout,
+                }
+            }
+        };
+
+        error!("{}", err);
+        Err(err)
+    }
+
+    /// queue the command, waiting up to `timeout` for room.  A queue that stays full returns
+    /// `QueueFull`; it is not counted as a timeout against the worker.
+    async fn wait_for_room(
+        &self,
+        route: usize,
+        timeout: Duration,
+        command: W::Command,
+    ) -> Result<(usize, W, u64), SupervisorError> {
+        let (worker, generation) = match self.shared.read_slots().get(route) {
+            Some(slot) => (slot.worker.clone(), slot.generation),
+            None => return Err(SupervisorError::NoWorker(route)),
+        };
+
+        match future::timeout(timeout, worker.request_channel().send(command)).await {
+            Ok(Ok(())) => Ok((route, worker, generation)),
+            Ok(Err(_)) => Err(SupervisorError::ChannelDown {
+                route,
+                worker_id: worker.id(),
+            }),
+            Err(_) => {
+                let err = SupervisorError::QueueFull {
+                    route,
+                    worker_id: worker.id(),
+                    capacity: self.shared.queue_capacity,
+                };
+                warn!("{}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// queue the command without waiting; under `Shed` a full queue passes a command that may be
+    /// shed on to the next worker.  Return the route, worker and generation that took the command.
+    fn enqueue(
+        &self,
+        route: usize,
+        command: W::Command,
+        shed: bool,
+    ) -> Result<(usize, W, u64), SupervisorError> {
+        let slots = self.shared.read_slots();
+        let Some(first) = slots.get(route) else {
+            return Err(SupervisorError::NoWorker(route));
+        };
+
+        let tries = match self.shared.backpressure {
+            Backpressure::Shed if shed => slots.len(),
+            _ => 1,
+        };
+
+        let mut command = command;
+        for n in 0..tries {
+            let next = (route + n) % slots.len();
+            let slot = &slots[next];
+            match slot.worker.request_channel().try_send(command) {
+                Ok(()) => return Ok((next, slot.worker.clone(), slot.generation)),
+                Err(TrySendError::Closed(_)) if next == route => {
+                    let worker_id = first.worker.id();
+                    return Err(SupervisorError::ChannelDown { route, worker_id });
+                }
+                Err(TrySendError::Full(c)) | Err(TrySendError::Closed(c)) => command = c,
+            }
+        }
+
+        let err = SupervisorError::QueueFull {
+            route,
+            worker_id: first.worker.id(),
+            capacity: self.shared.queue_capacity,
+        };
+        warn!("{}", err);
+        Err(err)
+    }
+
+    /// send the command to every worker concurrently and return each worker's result in route order
+    pub async fn request_all<R, F>(&self, command: F) -> Vec<Result<R, SupervisorError>>
+    where
+        F: Fn(Sender<R>) -> W::Command,
+    {
+        let routes = 0..self.workers().len();
+        join_all(routes.map(|route| self.request_route(route, &command))).await
+    }
+
+    /// return the status reported by the worker at the route
+    pub async fn worker_status(&self, route: usize) -> Result<WorkerStatus, SupervisorError> {
+        let json = self.request_route(route, W::status_command).await?;
+        serde_json::from_str(&json).map_err(|e| SupervisorError::Decode {
+            route,
+            worker_id: self.worker(route).map(|w| w.id()).unwrap_or_default(),
+            message: e.to_string(),
+        })
+    }
+
+    /// return the status of each worker in route order; the workers are queried concurrently.  If a
+    /// worker is non-responsive, send worker down response.  The supervisor's view is merged in:
+    /// timeouts add to the error count and a worker flagged as broken reports the `Broken` state.
+    pub async fn status(&self) -> Vec<WorkerStatus> {
+        let routes = 0..self.workers().len();
+        let results = join_all(routes.map(|route| self.worker_status(route))).await;
+
+        let mut status = vec![];
+        for (route, result) in results.into_iter().enumerate() {
+            let mut ws = match result {
+                Ok(ws) => ws,
+                Err(_) => match self.worker(route) {
+                    Some(worker) => WorkerStatus::worker_down(worker.id()),
+                    None => continue,
+                },
+            };
+
+            if let Some(slot) = self.shared.read_slots().get(route) {
+                let queue = slot.worker.request_channel();
+                ws.queue_len = queue.len();
+                ws.queue_capacity = queue.capacity();
+                ws.restart_count = slot.restart_count;
+                ws.error_count = ws.error_count.saturating_add(slot.timeouts);
+                if slot.broken {
+                    ws.state = WorkerState::Broken;
+                }
+            }
+
+            status.push(ws);
+        }
+
+        status
+    }
+}
+
+/// create a new worker and run its handler loop as a background task.  The task catches errors
+/// and panics from the handler and reports the exit back to the supervisor.  A successor replaces
+/// a recycled worker and is started with `WorkerTrait::successor_config`.
+fn start_worker<W: WorkerTrait>(
+    route: usize,
+    generation: u64,
+    shared: &Shared<W>,
+    exit_tx: Sender<WorkerExit>,
+    successor: bool,
+) -> (W, JoinHandle<Outcome>) {
+    let id = RouteKey::create();
+    info!("starting up worker, id: {}, route: {}", id, route);
+
+    let (request_tx, request_rx) = bounded(shared.queue_capacity);
+    let queue = request_rx.clone();
+    let worker = W::create(id.clone(), request_tx);
+    let config = if successor {
+        W::successor_config(&shared.worker_config, route)
+    } else {
+        W::route_config(&shared.worker_config, route)
+    };
+
+    let handle = task::spawn(async move {
+        let handler = AssertUnwindSafe(W::handler(id.clone(), config, request_rx));
+        let outcome = match handler.catch_unwind().await {
+            Ok(Ok(())) => {
+                info!("worker handler exit for worker id: {}", id);
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                error!("worker id: {} exit with error: {:?}", id, e);
+                Err(e.to_string())
+            }
+            Err(_) => {
+                error!("worker id: {} handler panicked", id);
+                Err("handler panicked".to_string())
+            }
+        };
+
+        // the worker handles keep the queue open, so drop the requests left in it; their callers
+        // get an error at once rather than waiting out the timeout
+        queue.close();
+        while queue.try_recv().is_ok() {}
+
+        let exit = WorkerExit {
+            route,
+            generation,
+            worker_id: id,
+            outcome: outcome.clone(),
+        };
+
+        // the channel is closed when the supervisor shuts down
+        let _ = exit_tx.send(exit).await;
+        outcome
+    });
+
+    (worker, handle)
+}
+
+/// the supervise loop; replaces workers that exit while the supervisor is running using the
+/// policy's strategy, backoff and restart intensity
+async fn supervise<W: WorkerTrait>(
+    shared: Arc<Shared<W>>,
+    policy: RestartPolicy,
+    exit_tx: Sender<WorkerExit>,
+    exit_rx: Receiver<WorkerExit>,
+) {
+    let mut intensity = RestartIntensity::default();
+    // the workers waiting on a restart's backoff, by route
+    let mut pending: HashMap<usize, u64> = HashMap::new();
+
+    while let Ok(exit) = exit_rx.recv().await {
+        if exit_rx.is_closed() {
+            break;
+        }
+
+        if !shared.is_current(exit.route, exit.generation) {
+            info!("ignore exit from replaced worker id: {}", exit.worker_id);
+            continue;
+        }
+
+        pending.retain(|route, generation| shared.is_current(*route, *generation));
+        if pending.get(&exit.route) == Some(&exit.generation) {
+            info!(
+                "worker id: {} is already waiting on a restart",
+                exit.worker_id
+            );
+            continue;
+        }
+
+        warn!(
+            "worker id: {}, route: {} exited: {:?}",
+            exit.worker_id, exit.route, exit.outcome
+        );
+
+        let count = intensity.record(Instant::now(), policy.period);
+        if count > policy.max_restarts {
+            error!(
+                "restart intensity exceeded, {} restarts in {:?}; supervisor failed",
+                count, policy.period
+            );
+
+            shared.set_state(SupervisorState::Failed);
+            exit_rx.close();
+
+            for worker in shared.workers().iter() {
+                let _ = worker.request_channel().try_send(W::shutdown_command());
+            }
+
+            break;
+        }
+
+        let delay = policy.backoff_delay(count);
+        info!("restart #{} in period after {:?}", count, delay);
+
+        // the backoff runs on its own so other routes are restarted in the meantime
+        let routes = policy
+            .strategy
+            .routes(exit.route, shared.read_slots().len());
+        for (route, generation, _) in shared.members() {
+            if routes.contains(&route) {
+                pending.insert(route, generation);
+            }
+        }
+        task::spawn(restart(
+            shared.clone(),
+            exit,
+            routes,
+            delay,
+            exit_tx.clone(),
+        ));
+    }
+
+    info!("supervise loop exit");
+}
+
+/// replace the workers in the routes after the backoff, unless the supervisor stopped or the
+/// exited worker was already replaced in the meantime
+async fn restart<W: WorkerTrait>(
+    shared: Arc<Shared<W>>,
+    exit: WorkerExit,
+    routes: Range<usize>,
+    delay: Duration,
+    exit_tx: Sender<WorkerExit>,
+) {
+    task::sleep(delay).await;
+
+    // shutdown may have started during the backoff
+    if exit_tx.is_closed() || shared.state() != SupervisorState::Running {
+        return;
+    }
+    if !shared.is_current(exit.route, exit.generation) {
+        return;
+    }
+
+    shared.replace(routes, &exit_tx).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::{HandlerFuture, Handoff, JsonString, WorkerState, OK};
+    use anyhow::anyhow;
+    use async_channel::Receiver;
+
+    /// a minimal worker that echos requests back to the caller
+    #[derive(Debug, Clone)]
+    struct EchoWorker {
+        id: String,
+        request_tx: Sender<EchoCommand>,
+    }
+
+    enum EchoCommand {
+        Echo(String, Sender<String>),
+        Sleep(Duration, Sender<()>),
+        Export(Sender<Handoff>),
+        Adopt(Sender<usize>),
+        Fail,
+        Panic,
+        Status(Sender<JsonString>),
+        Shutdown,
+    }
+
+    impl WorkerTrait for EchoWorker {
+        type Command = EchoCommand;
+        type Config = ();
+
+        fn create(id: String, request_tx: Sender<EchoCommand>) -> Self {
+            EchoWorker { id, request_tx }
+        }
+
+        fn handler(id: String, _config: (), rx: Receiver<EchoCommand>) -> HandlerFuture {
+            Box::pin(async move {
+                while let Ok(cmd) = rx.recv().await {
+                    match cmd {
+                        EchoCommand::Echo(msg, tx) => {
+                            let _ = tx.send(format!("{}:{}", id, msg)).await;
+                        }
+                        EchoCommand::Status(tx) => {
+                            let status = WorkerStatus::new(
+                                id.to_string(),
+                                OK.to_string(),
+                                WorkerState::Idle,
+                                String::new(),
+                                0,
+                            );
+                            let _ = tx.send(serde_json::to_string(&status)?).await;
+                        }
+                        EchoCommand::Sleep(delay, tx) => {
+                            task::sleep(delay).await;
+                            let _ = tx.send(()).await;
+                        }
+                        EchoCommand::Export(tx) => {
+                            // a slow handoff
+                            task::sleep(Duration::from_millis(200)).await;
+                            let _ = tx.send(Handoff::new(())).await;
+                        }
+                        EchoCommand::Adopt(tx) => {
+                            let _ = tx.send(0).await;
+                        }
+                        EchoCommand::Fail => return Err(anyhow!("worker id: {} failed", id)),
+                        EchoCommand::Panic => panic!("worker id: {} panicked", id),
+                        EchoCommand::Shutdown => break,
+                    }
+                }
+
+                Ok(())
+            })
+        }
+
+        fn status_command(tx: Sender<JsonString>) -> EchoCommand {
+            EchoCommand::Status(tx)
+        }
+
+        fn shutdown_command() -> EchoCommand {
+            EchoCommand::Shutdown
+        }
+
+        fn id(&self) -> String {
+            self.id.to_string()
+        }
+
+        fn request_channel(&self) -> Sender<EchoCommand> {
+            self.request_tx.clone()
+        }
+
+        fn export_command(tx: Sender<Handoff>) -> Option<EchoCommand> {
+            Some(EchoCommand::Export(tx))
+        }
+
+        fn adopt_command(_entries: Handoff, tx: Sender<usize>) -> Option<EchoCommand> {
+            Some(EchoCommand::Adopt(tx))
+        }
+    }
+
+    #[test]
+    fn generic_pool() {
+        async_std::task::block_on(async move {
+            let pool_size = 3;
+            let supervisor: Supervisor<EchoWorker> = Supervisor::new(pool_size)
+                .await
+                .expect("should create the supervisor");
+            assert_eq!(supervisor.workers().len(), pool_size);
+
+            let status = supervisor.status().await;
+            assert_eq!(status.len(), pool_size);
+            for (sts, worker) in status.iter().zip(supervisor.workers().iter()) {
+                assert_eq!(sts.worker_id, worker.id());
+                assert_eq!(sts.status, OK);
+            }
+
+            for route in 0..pool_size {
+                let resp = supervisor
+                    .request(route, |tx| EchoCommand::Echo("hello".to_string(), tx))
+                    .await
+                    .expect("should echo");
+                assert_eq!(resp, format!("{}:hello", supervisor.workers()[route].id()));
+            }
+
+            let resp = supervisor
+                .request(pool_size, |tx| EchoCommand::Echo("nope".to_string(), tx))
+                .await;
+            assert!(resp.is_err());
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    /// wait for the worker at the route to be replaced; return the new worker
+    async fn wait_for_restart(
+        supervisor: &Supervisor<EchoWorker>,
+        route: usize,
+        id: &str,
+    ) -> EchoWorker {
+        for _ in 0..200 {
+            if let Some(worker) = supervisor.worker(route) {
+                if worker.id() != id {
+                    return worker;
+                }
+            }
+            task::sleep(Duration::from_millis(10)).await;
+        }
+
+        panic!("worker at route {} was not restarted", route);
+    }
+
+    #[test]
+    fn restart_failed_workers() {
+        async_std::task::block_on(async move {
+            let pool_size = 3;
+            let supervisor: Supervisor<EchoWorker> = Supervisor::new(pool_size)
+                .await
+                .expect("should create the supervisor");
+
+            // an error exit from the handler
+            let route = 1;
+            let failed = supervisor.worker(route).unwrap();
+            let tx = failed.request_channel();
+            assert!(tx.send(EchoCommand::Fail).await.is_ok());
+
+            let worker = wait_for_restart(&supervisor, route, &failed.id()).await;
+            assert_eq!(supervisor.restart_count(route), 1);
+            assert!(tx.send(EchoCommand::Fail).await.is_err());
+
+            let resp = supervisor
+                .request(route, |tx| EchoCommand::Echo("again".to_string(), tx))
+                .await
+                .expect("the replacement should respond");
+            assert_eq!(resp, format!("{}:again", worker.id()));
+
+            // a panic in the handler
+            let tx = worker.request_channel();
+            assert!(tx.send(EchoCommand::Panic).await.is_ok());
+            let worker = wait_for_restart(&supervisor, route, &worker.id()).await;
+            assert_eq!(supervisor.restart_count(route), 2);
+
+            let status = supervisor.status().await;
+            assert_eq!(status[route].worker_id, worker.id());
+            assert_eq!(status[route].restart_count, 2);
+            assert_eq!(status[0].restart_count, 0);
+            assert_eq!(status[2].restart_count, 0);
+
+            // workers are not restarted after shutdown
+            assert!(supervisor.shutdown().await.is_ok());
+            task::sleep(Duration::from_millis(50)).await;
+            assert_eq!(supervisor.restart_count(route), 2);
+            assert_eq!(supervisor.worker(route).unwrap().id(), worker.id());
+        });
+    }
+
+    #[test]
+    fn backoff_per_route() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                restart: RestartPolicy {
+                    backoff: Duration::from_millis(500),
+                    max_backoff: Duration::from_millis(500),
+                    ..RestartPolicy::default()
+                },
+                health: None,
+                ..SupervisorConfig::default()
+            };
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+
+            // the second route's backoff does not wait behind the first
+            let started = Instant::now();
+            let failed: Vec<EchoWorker> = supervisor.workers();
+            for worker in failed.iter() {
+                assert!(worker
+                    .request_channel()
+                    .send(EchoCommand::Fail)
+                    .await
+                    .is_ok());
+            }
+            for (route, worker) in failed.iter().enumerate() {
+                wait_for_restart(&supervisor, route, &worker.id()).await;
+            }
+            assert!(started.elapsed() < Duration::from_millis(900));
+            assert_eq!(supervisor.restart_count(0), 1);
+            assert_eq!(supervisor.restart_count(1), 1);
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    fn config(strategy: RestartStrategy) -> SupervisorConfig {
+        SupervisorConfig {
+            restart: RestartPolicy {
+                strategy,
+                ..RestartPolicy::default()
+            },
+            ..SupervisorConfig::default()
+        }
+    }
+
+    #[test]
+    fn one_for_all() {
+        async_std::task::block_on(async move {
+            let config = config(RestartStrategy::OneForAll);
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(3, config)
+                .await
+                .expect("should create the supervisor");
+            let before = supervisor.workers();
+
+            let tx = before[1].request_channel();
+            assert!(tx.send(EchoCommand::Fail).await.is_ok());
+            wait_for_restart(&supervisor, 1, &before[1].id()).await;
+
+            let after = supervisor.workers();
+            for route in 0..3 {
+                assert_ne!(before[route].id(), after[route].id());
+                assert_eq!(supervisor.restart_count(route), 1);
+                let resp = supervisor
+                    .request(route, |tx| EchoCommand::Echo("ok".to_string(), tx))
+                    .await;
+                assert!(resp.is_ok());
+            }
+
+            assert!(supervisor.shutdown().await.is_ok());
+            assert_eq!(supervisor.state(), SupervisorState::Shutdown);
+        });
+    }
+
+    #[test]
+    fn rest_for_one() {
+        async_std::task::block_on(async move {
+            let config = config(RestartStrategy::RestForOne);
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(3, config)
+                .await
+                .expect("should create the supervisor");
+            let before = supervisor.workers();
+
+            let tx = before[1].request_channel();
+            assert!(tx.send(EchoCommand::Panic).await.is_ok());
+            wait_for_restart(&supervisor, 1, &before[1].id()).await;
+
+            let after = supervisor.workers();
+            assert_eq!(before[0].id(), after[0].id());
+            assert_ne!(before[1].id(), after[1].id());
+            assert_ne!(before[2].id(), after[2].id());
+            assert_eq!(supervisor.restart_count(0), 0);
+            assert_eq!(supervisor.restart_count(2), 1);
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn restart_intensity_exceeded() {
+        async_std::task::block_on(async move {
+            let mut config = config(RestartStrategy::OneForOne);
+            config.restart.max_restarts = 2;
+
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+
+            for _ in 0..2 {
+                let worker = supervisor.worker(0).unwrap();
+                assert!(worker
+                    .request_channel()
+                    .send(EchoCommand::Fail)
+                    .await
+                    .is_ok());
+                wait_for_restart(&supervisor, 0, &worker.id()).await;
+            }
+            assert_eq!(supervisor.state(), SupervisorState::Running);
+
+            // the third failure exceeds the intensity
+            let worker = supervisor.worker(0).unwrap();
+            assert!(worker
+                .request_channel()
+                .send(EchoCommand::Fail)
+                .await
+                .is_ok());
+            for _ in 0..200 {
+                if supervisor.state() == SupervisorState::Failed {
+                    break;
+                }
+                task::sleep(Duration::from_millis(10)).await;
+            }
+
+            assert_eq!(supervisor.state(), SupervisorState::Failed);
+            assert_eq!(supervisor.restart_count(0), 2);
+
+            let resp = supervisor
+                .request(1, |tx| EchoCommand::Echo("failed".to_string(), tx))
+                .await;
+            assert!(resp.is_err());
+
+            // shutdown does not hide the failure
+            assert!(supervisor.shutdown().await.is_ok());
+            assert_eq!(supervisor.state(), SupervisorState::Failed);
+        });
+    }
+
+    #[test]
+    fn request_timeout() {
+        async_std::task::block_on(async move {
+            let mut config = config(RestartStrategy::OneForOne);
+            config.max_timeouts = 2;
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+            assert_eq!(supervisor.timeout(), Duration::from_secs(5));
+
+            let quick = supervisor.with_timeout(Duration::from_millis(20));
+            assert_eq!(quick.timeout(), Duration::from_millis(20));
+
+            let slow = Duration::from_millis(100);
+            let err = quick
+                .request(1, |tx| EchoCommand::Sleep(slow, tx))
+                .await
+                .expect_err("should time out");
+            match err {
+                SupervisorError::Timeout { route, timeout, .. } => {
+                    assert_eq!(route, 1);
+                    assert_eq!(timeout, Duration::from_millis(20));
+                }
+                other => panic!("should be a timeout: {:?}", other),
+            }
+            assert!(!supervisor.is_broken(1));
+
+            // the per-call timeout
+            let r = supervisor
+                .request_with_timeout(1, Duration::from_millis(10), |tx| {
+                    EchoCommand::Sleep(slow, tx)
+                })
+                .await;
+            assert!(r.is_err());
+            assert!(supervisor.is_broken(1));
+            assert!(!supervisor.is_broken(0));
+
+            // dropping the handle does not stop the pool
+            drop(quick);
+            task::sleep(Duration::from_millis(250)).await;
+            let status = supervisor.status().await;
+            assert_eq!(status[1].state, WorkerState::Broken);
+            assert_eq!(status[1].error_count, 2);
+            assert_eq!(status[0].state, WorkerState::Idle);
+            assert_eq!(status[0].error_count, 0);
+
+            // a broken worker still answers within the default timeout
+            let r = supervisor
+                .request(1, |tx| EchoCommand::Echo("late".to_string(), tx))
+                .await;
+            assert!(r.is_ok());
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn heartbeat() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                health: Some(HealthConfig {
+                    interval: Duration::from_millis(20),
+                    deadline: Duration::from_millis(10),
+                    max_missed: 2,
+                }),
+                ..SupervisorConfig::default()
+            };
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+            let events = supervisor.subscribe_health();
+            let hung = supervisor.worker(1).unwrap().id();
+
+            // the worker stops answering
+            let (responder, _rx) = bounded(1);
+            let tx = supervisor.worker(1).unwrap().request_channel();
+            let sleep = EchoCommand::Sleep(Duration::from_secs(10), responder);
+            assert!(tx.send(sleep).await.is_ok());
+
+            let mut received = vec![];
+            loop {
+                let event = future::timeout(Duration::from_secs(2), events.recv())
+                    .await
+                    .expect("should get a health event")
+                    .unwrap();
+                assert_eq!(event.route(), 1);
+                received.push(event.clone());
+                if let HealthEvent::Replaced { .. } = event {
+                    break;
+                }
+            }
+
+            let unresponsive = |missed| HealthEvent::Unresponsive {
+                route: 1,
+                worker_id: hung.to_string(),
+                missed,
+            };
+            assert_eq!(received[0], unresponsive(1));
+            assert_eq!(received[1], unresponsive(2));
+            assert!(matches!(received[2], HealthEvent::Broken { .. }));
+            // the hung worker was stopped before its replacement started
+            assert!(tx.is_closed());
+            let replacement = supervisor.worker(1).unwrap().id();
+            assert_ne!(replacement, hung);
+            let replaced = HealthEvent::Replaced {
+                route: 1,
+                worker_id: hung,
+                new_worker_id: replacement,
+            };
+            assert_eq!(received[3], replaced);
+
+            // the replacement answers and is healthy
+            assert_eq!(supervisor.restart_count(1), 1);
+            assert!(!supervisor.is_broken(1));
+            let r = supervisor
+                .request(1, |tx| EchoCommand::Echo("hello".to_string(), tx))
+                .await;
+            assert!(r.is_ok());
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn recycle_one_route() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                health: None,
+                ..SupervisorConfig::default()
+            };
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(2, config)
+                .await
+                .expect("should create the supervisor");
+            let old = supervisor.worker(0).unwrap().id();
+
+            let recycle = {
+                let shared = supervisor.shared.clone();
+                let exit_tx = supervisor.exit_guard.0.clone();
+                let generation = shared.read_slots()[0].generation;
+                assert!(shared.start_recycle(0, generation));
+                assert!(!shared.start_recycle(0, generation));
+                task::spawn(async move {
+                    let reason = "test".to_string();
+                    shared.recycle(0, generation, reason, &exit_tx).await
+                })
+            };
+            task::sleep(Duration::from_millis(20)).await;
+
+            // the other route answers during the handoff
+            let started = Instant::now();
+            let echo = |tx| EchoCommand::Echo("x".to_string(), tx);
+            assert!(supervisor.request(1, echo).await.is_ok());
+            assert!(started.elapsed() < Duration::from_millis(100));
+
+            // the recycled route waits and is answered by the new worker
+            let resp = supervisor.request(0, echo).await.unwrap();
+            assert!(recycle.await);
+            let new = supervisor.worker(0).unwrap().id();
+            assert_ne!(new, old);
+            assert_eq!(resp, format!("{}:x", new));
+            assert_eq!(supervisor.restart_count(0), 1);
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn graceful_shutdown() {
+        async_std::task::block_on(async move {
+            let supervisor: Supervisor<EchoWorker> = Supervisor::new(2)
+                .await
+                .expect("should create the supervisor");
+
+            // queue requests that finish after the shutdown starts
+            let pending: Vec<_> = (0..3)
+                .map(|_| {
+                    let supervisor = supervisor.with_timeout(Duration::from_secs(1));
+                    let delay = Duration::from_millis(20);
+                    task::spawn(async move {
+                        supervisor
+                            .request(0, |tx| EchoCommand::Sleep(delay, tx))
+                            .await
+                    })
+                })
+                .collect();
+            task::sleep(Duration::from_millis(5)).await;
+
+            let report = supervisor.shutdown_graceful(Duration::from_secs(1)).await;
+            assert!(report.is_clean());
+            assert_eq!(report.workers.len(), 2);
+            assert_eq!(report.workers[0].route, 0);
+            for request in pending {
+                assert!(request.await.is_ok());
+            }
+
+            let echo = supervisor.request(1, |tx| EchoCommand::Echo("x".to_string(), tx));
+            assert_eq!(echo.await, Err(SupervisorError::Shutdown));
+
+            // a worker that does not drain by the deadline is aborted
+            let supervisor: Supervisor<EchoWorker> = Supervisor::new(2)
+                .await
+                .expect("should create the supervisor");
+            let (responder, _rx) = bounded(1);
+            let tx = supervisor.worker(1).unwrap().request_channel();
+            let sleep = EchoCommand::Sleep(Duration::from_secs(10), responder);
+            assert!(tx.send(sleep).await.is_ok());
+
+            let started = Instant::now();
+            let report = supervisor
+                .shutdown_graceful(Duration::from_millis(50))
+                .await;
+            assert!(started.elapsed() < Duration::from_secs(1));
+            assert!(!report.is_clean());
+            assert_eq!(report.workers[0].exit, ExitKind::Clean);
+            assert_eq!(report.workers[1].exit, ExitKind::Aborted);
+        });
+    }
+
+    #[test]
+    fn shutdown_race() {
+        async_std::task::block_on(async move {
+            let supervisor: Supervisor<EchoWorker> = Supervisor::new(1)
+                .await
+                .expect("should create the supervisor");
+
+            // the worker is busy with a shutdown queued when the request arrives
+            let tx = supervisor.worker(0).unwrap().request_channel();
+            let (responder, _rx) = bounded(1);
+            let sleep = EchoCommand::Sleep(Duration::from_millis(30), responder);
+            assert!(tx.send(sleep).await.is_ok());
+            assert!(tx.send(EchoCommand::Shutdown).await.is_ok());
+            let request = {
+                let supervisor = supervisor.with_timeout(Duration::from_secs(1));
+                task::spawn(async move {
+                    supervisor
+                        .request(0, |tx| EchoCommand::Echo("late".to_string(), tx))
+                        .await
+                })
+            };
+            task::sleep(Duration::from_millis(5)).await;
+
+            let report = supervisor.shutdown_graceful(Duration::from_secs(1)).await;
+            assert!(report.is_clean());
+            assert_eq!(request.await, Err(SupervisorError::Shutdown));
+        });
+    }
+
+    #[test]
+    fn backpressure() {
+        async_std::task::block_on(async move {
+            // a pool of two workers with worker 0 busy and its one slot queue full
+            let pool = |backpressure| async move {
+                let config = SupervisorConfig {
+                    queue_capacity: 1,
+                    backpressure,
+                    health: None,
+                    ..SupervisorConfig::default()
+                };
+                let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(2, config)
+                    .await
+                    .expect("should create the supervisor");
+
+                let tx = supervisor.worker(0).unwrap().request_channel();
+                let (responder, _rx) = bounded(10);
+                for _ in 0..2 {
+                    let sleep = EchoCommand::Sleep(Duration::from_millis(200), responder.clone());
+                    assert!(tx.send(sleep).await.is_ok());
+                }
+                assert!(tx.is_full());
+
+                supervisor
+            };
+            let echo = |tx| EchoCommand::Echo("x".to_string(), tx);
+
+            let supervisor = pool(Backpressure::FailFast).await;
+            let err = supervisor.request(0, echo).await.unwrap_err();
+            assert!(matches!(
+                err,
+                SupervisorError::QueueFull {
+                    route: 0,
+                    capacity: 1,
+                    ..
+                }
+            ));
+            let status = supervisor.status().await;
+            assert_eq!(status[1].queue_capacity, Some(1));
+            assert_eq!(status[1].queue_fill(), Some(0.0));
+            assert!(supervisor.shutdown().await.is_ok());
+
+            let supervisor = pool(Backpressure::Shed).await;
+            let other = supervisor.worker(1).unwrap().id();
+            let resp = supervisor.request(0, echo).await.unwrap();
+            assert_eq!(resp, format!("{}:x", other));
+
+            // requests meant for the full worker are not shed
+            let results = supervisor.request_all(echo).await;
+            assert!(matches!(results[0], Err(SupervisorError::QueueFull { .. })));
+            assert!(results[1].is_ok());
+            let status = supervisor.worker_status(0).await;
+            assert!(matches!(status, Err(SupervisorError::QueueFull { .. })));
+            assert!(supervisor.shutdown().await.is_ok());
+
+            // a queue that stays full is not the worker's timeout
+            let supervisor = pool(Backpressure::Block).await;
+            let err = supervisor
+                .request_with_timeout(0, Duration::from_millis(50), echo)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, SupervisorError::QueueFull { route: 0, .. }));
+            assert_eq!(supervisor.shared.read_slots()[0].timeouts, 0);
+
+            // a request that gets in waits out the rest of the timeout for the response
+            let started = Instant::now();
+            let err = supervisor
+                .request_with_timeout(0, Duration::from_millis(300), echo)
+                .await
+                .unwrap_err();
+            assert!(err.is_timeout());
+            assert!(started.elapsed() < Duration::from_millis(400));
+            assert_eq!(supervisor.shared.read_slots()[0].timeouts, 1);
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn request_errors() {
+        async_std::task::block_on(async move {
+            let supervisor: Supervisor<EchoWorker> = Supervisor::new(1)
+                .await
+                .expect("should create the supervisor");
+
+            let err = supervisor
+                .request(3, |tx| EchoCommand::Echo("nope".to_string(), tx))
+                .await
+                .expect_err("should not route");
+            assert_eq!(err, SupervisorError::NoWorker(3));
+            assert_eq!(err.route(), Some(3));
+
+            assert!(supervisor.shutdown().await.is_ok());
+            task::sleep(Duration::from_millis(20)).await;
+
+            let err = supervisor
+                .request(0, |tx| EchoCommand::Echo("down".to_string(), tx))
+                .await
+                .expect_err("should be down");
+            assert!(matches!(err, SupervisorError::ChannelDown { route: 0, .. }));
+        });
+    }
+
+    #[test]
+    fn partial_results() {
+        async_std::task::block_on(async move {
+            let mut config = config(RestartStrategy::OneForOne);
+            config.restart.backoff = Duration::from_secs(5);
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(3, config)
+                .await
+                .expect("should create the supervisor");
+
+            // the failed worker is not replaced until after the backoff
+            let failed = supervisor.worker(1).unwrap();
+            assert!(failed
+                .request_channel()
+                .send(EchoCommand::Fail)
+                .await
+                .is_ok());
+            task::sleep(Duration::from_millis(20)).await;
+
+            let results = supervisor
+                .request_all(|tx| EchoCommand::Echo("all".to_string(), tx))
+                .await;
+            assert_eq!(results.len(), 3);
+            assert!(results[0].is_ok());
+            assert!(results[2].is_ok());
+
+            let err = PartialError::combine(results, vec![], |list, resp| list.push(resp))
+                .expect_err("should be partial");
+            assert_eq!(err.partial.len(), 2);
+            assert_eq!(err.failed_routes(), vec![1]);
+            assert!(matches!(
+                &err.errors[0],
+                SupervisorError::ChannelDown { worker_id, .. } if *worker_id == failed.id()
+            ));
+
+            let status = supervisor.status().await;
+            assert_eq!(status[1].status, crate::worker::DOWN);
+            assert_eq!(status[1].worker_id, failed.id());
+
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn routers() {
+        async_std::task::block_on(async move {
+            let config = SupervisorConfig {
+                router: Arc::new(routing::LeastLoadedRouter::default()),
+                ..SupervisorConfig::default()
+            };
+            let supervisor: Supervisor<EchoWorker> = Supervisor::with_config(3, config)
+                .await
+                .expect("should create the supervisor");
+
+            // back up worker 1's queue behind a slow request
+            let tx = supervisor.worker(1).unwrap().request_channel();
+            let (responder, _rx) = bounded(10);
+            for _ in 0..4 {
+                let sleep = EchoCommand::Sleep(Duration::from_millis(200), responder.clone());
+                assert!(tx.send(sleep).await.is_ok());
+            }
+            assert!(supervisor.queue_len(1) >= 3);
+
+            for _ in 0..6 {
+                let route = supervisor.get_route("any").unwrap();
+                assert_ne!(route, 1);
+            }
+
+            let round_robin = SupervisorConfig {
+                router: Arc::new(routing::RoundRobinRouter::default()),
+                ..SupervisorConfig::default()
+            };
+            let pool: Supervisor<EchoWorker> = Supervisor::with_config(2, round_robin)
+                .await
+                .expect("should create the supervisor");
+            let routes: Vec<usize> = (0..4).map(|_| pool.get_route("same").unwrap()).collect();
+            assert_eq!(routes, vec![0, 1, 0, 1]);
+
+            assert!(pool.shutdown().await.is_ok());
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn resize_stateless() {
+        async_std::task::block_on(async move {
+            let supervisor: Supervisor<EchoWorker> = Supervisor::new(2)
+                .await
+                .expect("should create the supervisor");
+
+            assert!(supervisor.resize(4).await.is_ok());
+            assert_eq!(supervisor.pool_size(), 4);
+            let resp = supervisor
+                .request(3, |tx| EchoCommand::Echo("new".to_string(), tx))
+                .await;
+            assert_eq!(
+                resp,
+                Ok(format!("{}:new", supervisor.worker(3).unwrap().id()))
+            );
+
+            let drained = supervisor.worker(1).unwrap();
+            assert!(supervisor.resize(1).await.is_ok());
+            assert_eq!(supervisor.status().await.len(), 1);
+            assert_eq!(supervisor.get_route("any"), Ok(0));
+            task::sleep(Duration::from_millis(20)).await;
+            assert!(drained.request_channel().is_closed());
+
+            // the drained worker's exit does not trigger a restart
+            assert_eq!(supervisor.restart_count(0), 0);
+            assert!(supervisor.shutdown().await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,179 @@
+/// worker recycling; replaces a healthy worker and hands its entries over to the new one
+use super::health::HealthEvent;
+use super::{start_worker, Shared, SupervisorState, WorkerExit, WorkerSlot};
+use crate::worker::{Handoff, WorkerStatus, WorkerTrait};
+use async_channel::{bounded, Receiver, Sender};
+use async_std::future;
+use log::*;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// the limits after which a worker is replaced; no limit if None.  The limits are checked on each
+/// heartbeat, so recycling needs the health monitor.
+#[derive(Debug, Default, Clone)]
+pub struct RecycleConfig {
+    /// the time a worker runs before it is replaced; only for workers that report their uptime
+    pub max_uptime: Option<Duration>,
+    /// the number of commands a worker handles before it is replaced
+    pub max_commands: Option<u64>,
+    /// the number of errors a worker reports before it is replaced
+    pub max_errors: Option<u16>,
+}
+
+impl RecycleConfig {
+    /// return the reason the worker should be replaced, or None if it is within the limits
+    pub fn due<W: WorkerTrait>(&self, worker: &W, status: &WorkerStatus) -> Option<String> {
+        if let (Some(max), Some(uptime)) = (self.max_uptime, worker.uptime_seconds()) {
+            if uptime >= max.as_secs() {
+                return Some(format!("uptime {}s reached the limit", uptime));
+            }
+        }
+
+        if let Some(max) = self.max_commands.filter(|max| status.command_count >= *max) {
+            return Some(format!(
+                "{} commands reached the limit of {}",
+                status.command_count, max
+            ));
+        }
+
+        if let Some(max) = self.max_errors.filter(|max| status.error_count >= *max) {
+            return Some(format!(
+                "{} errors reached the limit of {}",
+                status.error_count, max
+            ));
+        }
+
+        None
+    }
+}
+
+/// send the command and wait up to the timeout for the answer
+async fn call<W: WorkerTrait, R>(
+    worker: &W,
+    command: W::Command,
+    rx: Receiver<R>,
+    timeout: Duration,
+) -> Option<R> {
+    let answer = future::timeout(timeout, async move {
+        worker.request_channel().send(command).await.ok()?;
+        rx.recv().await.ok()
+    });
+
+    answer.await.ok().flatten()
+}
+
+/// copy every entry from the old worker to its successor; return the number adopted, or None if
+/// the handoff failed
+async fn hand_off<W: WorkerTrait>(from: &W, to: &W, timeout: Duration) -> Option<usize> {
+    let (tx, rx) = bounded(1);
+    let Some(command) = W::export_command(tx) else {
+        return Some(0);
+    };
+    let entries: Handoff = call(from, command, rx, timeout).await?;
+
+    let (tx, rx) = bounded(1);
+    let command = W::adopt_command(entries, tx)?;
+
+    call(to, command, rx, timeout).await
+}
+
+impl<W: WorkerTrait> Shared<W> {
+    /// mark the worker as being recycled; return false if it was replaced or is already being
+    /// recycled
+    pub(super) fn start_recycle(&self, route: usize, generation: u64) -> bool {
+        match self.write_slots().get_mut(route) {
+            Some(slot) if slot.generation == generation && !slot.recycling => {
+                slot.recycling = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// replace the route's worker with a new one after copying over its entries; return true if
+    /// the worker was replaced.  A worker that stays in place may be recycled again later.
+    pub(super) async fn recycle(
+        &self,
+        route: usize,
+        generation: u64,
+        reason: String,
+        exit_tx: &Sender<WorkerExit>,
+    ) -> bool {
+        let recycled = self.succeed(route, generation, reason, exit_tx).await;
+        if !recycled {
+            let mut slots = self.write_slots();
+            if let Some(slot) = slots.get_mut(route).filter(|s| s.generation == generation) {
+                slot.recycling = false;
+            }
+        }
+
+        recycled
+    }
+
+    /// start the successor, hand it the entries and swap it into the slot
+    async fn succeed(
+        &self,
+        route: usize,
+        generation: u64,
+        reason: String,
+        exit_tx: &Sender<WorkerExit>,
+    ) -> bool {
+        // wait out a resize, then hold back the route's requests until the entries are copied
+        let _resizing = self.resizing.lock().await;
+        let Some((old, gate)) = self
+            .read_slots()
+            .get(route)
+            .filter(|slot| slot.generation == generation)
+            .map(|slot| (slot.worker.clone(), slot.gate.clone()))
+        else {
+            return false;
+        };
+        let _gate = gate.write().await;
+        if self.state() != SupervisorState::Running || !self.is_current(route, generation) {
+            return false;
+        }
+        info!(
+            "recycle worker id: {}, route: {}: {}",
+            old.id(),
+            route,
+            reason
+        );
+
+        let next = self.generation.fetch_add(1, Ordering::SeqCst);
+        let (worker, handle) = start_worker::<W>(route, next, self, exit_tx.clone(), true);
+
+        let Some(count) = hand_off(&old, &worker, self.request_timeout).await else {
+            warn!("recycle worker id: {} failed, handoff incomplete", old.id());
+            let _ = worker.request_channel().send(W::shutdown_command()).await;
+            return false;
+        };
+        info!("handed {} entries to worker id: {}", count, worker.id());
+
+        let swapped = match self.write_slots().get_mut(route) {
+            Some(slot) if slot.generation == generation => {
+                let restart_count = slot.restart_count + 1;
+                *slot = WorkerSlot::new(worker.clone(), handle, next);
+                slot.restart_count = restart_count;
+                // requests waiting on the gate go to the new worker
+                slot.gate = gate.clone();
+                true
+            }
+            _ => false,
+        };
+        if !swapped {
+            let _ = worker.request_channel().send(W::shutdown_command()).await;
+            return false;
+        }
+
+        // the old worker's exit is ignored now that its generation is replaced
+        let _ = old.request_channel().send(W::shutdown_command()).await;
+        self.health.publish(HealthEvent::Recycled {
+            route,
+            worker_id: old.id(),
+            new_worker_id: worker.id(),
+            reason,
+        });
+
+        true
+    }
+}
@@ -0,0 +1,220 @@
+/// online pool resizing; keyed entries move to the worker that owns them after the resize
+use super::{Supervisor, SupervisorError, SupervisorState};
+use crate::worker::{Handoff, WorkerTrait};
+use async_channel::bounded;
+use async_std::sync::RwLockReadGuard;
+use log::*;
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+
+impl<W: WorkerTrait> Supervisor<W> {
+    /// the number of workers keys are routed to
+    pub fn pool_size(&self) -> usize {
+        self.shared.pool_size.load(Ordering::SeqCst)
+    }
+
+    /// the pool size before the resize in progress, if any
+    pub fn previous_size(&self) -> Option<usize> {
+        *self
+            .shared
+            .previous_size
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// while a resize is migrating entries, return the key's route before the resize if it
+    /// differs from the current one
+    pub fn previous_route(&self, key: &str) -> Option<usize> {
+        let size = self.previous_size()?;
+        let prev = self.route_for_size(key, size).ok()?;
+        match self.get_route(key) {
+            Ok(route) if route == prev => None,
+            _ => Some(prev),
+        }
+    }
+
+    /// hold this while routing a keyed request and talking to the workers that own the key, so
+    /// that entries do not move between workers in the middle of the request
+    pub async fn routing_guard(&self) -> RwLockReadGuard<'_, ()> {
+        self.shared.routing_lock.read().await
+    }
+
+    /// add workers or drain the workers past `new_size`.  Keyed entries are migrated to their
+    /// new owners before drained workers are shut down.
+    pub async fn resize(&self, new_size: usize) -> Result<(), SupervisorError> {
+        if new_size == 0 {
+            return Err(SupervisorError::InvalidSize(new_size));
+        }
+
+        let _resizing = self.shared.resizing.lock().await;
+        match self.state() {
+            SupervisorState::Running => (),
+            SupervisorState::Failed => return Err(SupervisorError::Failed),
+            SupervisorState::Shutdown => return Err(SupervisorError::Shutdown),
+        }
+
+        let old_size = self.pool_size();
+        if new_size == old_size {
+            return Ok(());
+        }
+
+        info!("resize pool from {} to {} workers", old_size, new_size);
+        if new_size > old_size {
+            self.shared.grow(new_size, &self.exit_guard.0);
+        }
+
+        {
+            let _routing = self.shared.routing_lock.write().await;
+            self.set_previous_size(Some(old_size));
+            self.shared.pool_size.store(new_size, Ordering::SeqCst);
+        }
+
+        let count = match self.migrate(old_size).await {
+            Ok(count) => count,
+            Err(e) => {
+                error!("resize to {} workers failed: {}", new_size, e);
+                self.roll_back(old_size, new_size).await;
+                return Err(e);
+            }
+        };
+
+        {
+            let _routing = self.shared.routing_lock.write().await;
+            self.set_previous_size(None);
+        }
+
+        if new_size < old_size {
+            self.drain(new_size).await;
+        }
+
+        info!("resize complete, {} entries migrated", count);
+
+        Ok(())
+    }
+
+    /// return to the old size after a failed migration and move the entries that were migrated
+    /// back to their old owners.  The workers started for the resize are drained once their
+    /// entries are back; if that fails they are kept and reads still fall back to them.
+    async fn roll_back(&self, old_size: usize, new_size: usize) {
+        {
+            let _routing = self.shared.routing_lock.write().await;
+            self.set_previous_size(Some(new_size));
+            self.shared.pool_size.store(old_size, Ordering::SeqCst);
+        }
+
+        if let Err(e) = self.migrate(old_size.max(new_size)).await {
+            error!(
+                "resize roll back incomplete, {} workers kept: {}",
+                new_size, e
+            );
+            return;
+        }
+
+        {
+            let _routing = self.shared.routing_lock.write().await;
+            self.set_previous_size(None);
+        }
+
+        if new_size > old_size {
+            self.drain(old_size).await;
+        }
+        info!("resize rolled back to {} workers", old_size);
+    }
+
+    fn set_previous_size(&self, size: Option<usize>) {
+        *self
+            .shared
+            .previous_size
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = size;
+    }
+
+    /// move the entries whose route has changed from each of the first `routes` workers; return
+    /// the number of entries moved
+    async fn migrate(&self, routes: usize) -> Result<usize, SupervisorError> {
+        let mut count = 0;
+        for route in 0..routes {
+            let (tx, rx) = bounded(1);
+            let command = match W::keys_command(tx) {
+                Some(command) => command,
+                None => return Ok(0),
+            };
+
+            let keys = self
+                .dispatch(route, self.timeout, command, rx, false)
+                .await?;
+            let mut moves: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+            for key in keys {
+                let owner = self.get_route(&key)?;
+                if owner != route {
+                    moves.entry(owner).or_default().push(key);
+                }
+            }
+
+            for (owner, keys) in moves {
+                count += self.move_entries(route, owner, keys).await?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// take the keys from one worker and put them in another without letting a keyed request in
+    /// between.  If the put fails the entries are given back to the worker they came from.
+    async fn move_entries(
+        &self,
+        from: usize,
+        to: usize,
+        keys: Vec<String>,
+    ) -> Result<usize, SupervisorError> {
+        let _routing = self.shared.routing_lock.write().await;
+
+        let (tx, rx) = bounded(1);
+        let command = match W::take_command(keys, tx) {
+            Some(command) => command,
+            None => return Ok(0),
+        };
+        let entries: Handoff = self
+            .dispatch(from, self.timeout, command, rx, false)
+            .await?;
+        let copy = entries.clone();
+
+        match self.put_entries(to, entries).await {
+            Ok(count) => Ok(count),
+            Err(e) => {
+                warn!("put to route {} failed, give the entries back: {}", to, e);
+                if let Err(e) = self.put_entries(from, copy).await {
+                    error!("entries taken from route {} are lost: {}", from, e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// store the entries in the worker at the route; return the number stored
+    async fn put_entries(&self, route: usize, entries: Handoff) -> Result<usize, SupervisorError> {
+        let (tx, rx) = bounded(1);
+        let command = W::put_command(entries, tx).ok_or_else(|| SupervisorError::Decode {
+            route,
+            worker_id: self.worker(route).map(|w| w.id()).unwrap_or_default(),
+            message: "the worker does not accept the handoff entries".to_string(),
+        })?;
+
+        self.dispatch(route, self.timeout, command, rx, false).await
+    }
+
+    /// remove the workers past `size` from the pool and shut them down after their queued
+    /// requests
+    async fn drain(&self, size: usize) {
+        let drained: Vec<W> = {
+            let mut slots = self.shared.write_slots();
+            let keep = size.min(slots.len());
+            slots.drain(keep..).map(|slot| slot.worker).collect()
+        };
+
+        for worker in drained.iter() {
+            info!("drain worker id: {}", worker.id());
+            let _ = worker.request_channel().send(W::shutdown_command()).await;
+        }
+    }
+}
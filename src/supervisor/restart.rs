@@ -0,0 +1,131 @@
+/// restart strategies, intensity limits and backoff, modeled on the erlang/otp supervisor
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// decides which workers are restarted when one of them exits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RestartStrategy {
+    /// restart only the worker that exited
+    #[default]
+    OneForOne,
+    /// restart every worker in the pool
+    OneForAll,
+    /// restart the worker that exited and all workers with a higher route number
+    RestForOne,
+}
+
+impl RestartStrategy {
+    /// return the routes to restart when the worker at `route` exits
+    pub fn routes(&self, route: usize, pool_size: usize) -> Range<usize> {
+        match self {
+            RestartStrategy::OneForOne => route..route + 1,
+            RestartStrategy::OneForAll => 0..pool_size,
+            RestartStrategy::RestForOne => route..pool_size,
+        }
+    }
+}
+
+/// how and how often the supervisor restarts workers.  If more than `max_restarts` restarts
+/// happen within `period` the supervisor gives up and moves to the failed state.
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    pub strategy: RestartStrategy,
+    pub max_restarts: usize,
+    pub period: Duration,
+    /// the delay before the first restart in a period; doubles for each restart after that.  Each
+    /// restart waits on its own, so a backoff on one route does not hold up the others.
+    pub backoff: Duration,
+    /// the upper limit for the restart delay
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            strategy: RestartStrategy::OneForOne,
+            max_restarts: 10,
+            period: Duration::from_secs(60),
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// return the delay before the nth restart within the current period
+    pub fn backoff_delay(&self, nth: usize) -> Duration {
+        let exp = nth.saturating_sub(1).min(31) as u32;
+        let delay = self.backoff.saturating_mul(1_u32 << exp);
+
+        delay.min(self.max_backoff)
+    }
+}
+
+/// a sliding window of recent restarts used to enforce the restart intensity
+#[derive(Debug, Default)]
+pub struct RestartIntensity {
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartIntensity {
+    /// record a restart at `now`; return the number of restarts within the period
+    pub fn record(&mut self, now: Instant, period: Duration) -> usize {
+        while let Some(first) = self.restarts.front() {
+            if now.duration_since(*first) > period {
+                self.restarts.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        self.restarts.push_back(now);
+        self.restarts.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategy_routes() {
+        assert_eq!(RestartStrategy::OneForOne.routes(2, 5), 2..3);
+        assert_eq!(RestartStrategy::OneForAll.routes(2, 5), 0..5);
+        assert_eq!(RestartStrategy::RestForOne.routes(2, 5), 2..5);
+        assert_eq!(RestartStrategy::RestForOne.routes(4, 5), 4..5);
+    }
+
+    #[test]
+    fn backoff_delay() {
+        let policy = RestartPolicy {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..RestartPolicy::default()
+        };
+
+        assert_eq!(policy.backoff_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_delay(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_delay(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff_delay(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff_delay(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff_delay(500), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn intensity_window() {
+        let period = Duration::from_secs(10);
+        let mut intensity = RestartIntensity::default();
+        let start = Instant::now();
+
+        assert_eq!(intensity.record(start, period), 1);
+        assert_eq!(intensity.record(start + Duration::from_secs(2), period), 2);
+        assert_eq!(intensity.record(start + Duration::from_secs(9), period), 3);
+
+        // the first restart falls out of the window
+        assert_eq!(intensity.record(start + Duration::from_secs(11), period), 3);
+
+        // all previous restarts fall out of the window
+        assert_eq!(intensity.record(start + Duration::from_secs(30), period), 1);
+    }
+}
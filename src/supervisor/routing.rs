@@ -0,0 +1,359 @@
+/// routers that map requests to worker routes
+use crate::supervisor::error::SupervisorError;
+use domain_keys::keys::RouteKey;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+/// the router's view of the pool when it routes a request
+pub struct PoolView<'a> {
+    size: usize,
+    queue_len: &'a dyn Fn(usize) -> usize,
+}
+
+impl<'a> PoolView<'a> {
+    pub fn new(size: usize, queue_len: &'a dyn Fn(usize) -> usize) -> PoolView<'a> {
+        PoolView { size, queue_len }
+    }
+
+    /// the number of workers in the pool
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// the number of requests waiting in the worker's queue
+    pub fn queue_len(&self, route: usize) -> usize {
+        (self.queue_len)(route)
+    }
+}
+
+/// decides which worker handles a request
+pub trait Router: Debug + Send + Sync {
+    /// return the route for the key; must be less than the pool size
+    fn route(&self, key: &str, pool: &PoolView) -> Result<usize, SupervisorError>;
+}
+
+/// routes by the domain route key.  The route key only encodes up to 255 routes, so for larger
+/// pools the key is validated and then spread across the pool by hash.  Unless strict, keys that
+/// are not route keys are hashed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RouteKeyRouter {
+    strict: bool,
+}
+
+impl RouteKeyRouter {
+    /// a router that returns `SupervisorError::Route` for keys that are not route keys
+    pub fn strict() -> RouteKeyRouter {
+        RouteKeyRouter { strict: true }
+    }
+}
+
+impl Router for RouteKeyRouter {
+    fn route(&self, key: &str, pool: &PoolView) -> Result<usize, SupervisorError> {
+        let pool_size = pool.size();
+        let count = u8::try_from(pool_size.max(1)).unwrap_or(u8::MAX);
+        let route = match RouteKey::parse_route(key, count) {
+            Ok(route) => usize::from(route),
+            Err(_) if !self.strict => return Ok(hash_route(key, pool_size)),
+            Err(e) => {
+                return Err(SupervisorError::Route {
+                    key: key.to_string(),
+                    message: e.to_string(),
+                })
+            }
+        };
+
+        if pool_size > usize::from(u8::MAX) {
+            Ok(hash_route(key, pool_size))
+        } else {
+            Ok(route)
+        }
+    }
+}
+
+/// routes every key by its hash
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HashRouter;
+
+impl Router for HashRouter {
+    fn route(&self, key: &str, pool: &PoolView) -> Result<usize, SupervisorError> {
+        Ok(hash_route(key, pool.size()))
+    }
+}
+
+/// sends each request to the next worker in turn
+#[derive(Debug, Default)]
+pub struct RoundRobinRouter {
+    next: AtomicUsize,
+}
+
+impl Router for RoundRobinRouter {
+    fn route(&self, _key: &str, pool: &PoolView) -> Result<usize, SupervisorError> {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        Ok(next % pool.size().max(1))
+    }
+}
+
+/// sends each request to the worker with the shortest queue; ties are broken in turn so an idle
+/// pool does not hotspot the first worker
+#[derive(Debug, Default)]
+pub struct LeastLoadedRouter {
+    next: AtomicUsize,
+}
+
+impl Router for LeastLoadedRouter {
+    fn route(&self, _key: &str, pool: &PoolView) -> Result<usize, SupervisorError> {
+        let size = pool.size().max(1);
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let route = (0..size)
+            .map(|n| (start + n) % size)
+            .min_by_key(|route| pool.queue_len(*route))
+            .unwrap_or(0);
+
+        Ok(route)
+    }
+}
+
+/// the number of ring sizes a `ConsistentHashRouter` keeps; a resize routes by the old and new
+/// size until the entries have moved
+const RINGS: usize = 2;
+
+/// a hash ring with virtual nodes; a change in pool size only moves about `1 / size` of the keys
+#[derive(Debug)]
+pub struct ConsistentHashRouter {
+    vnodes: usize,
+    /// the most recently used rings, newest first
+    rings: RwLock<Vec<Ring>>,
+}
+
+#[derive(Debug, Default)]
+struct Ring {
+    size: usize,
+    points: Vec<(u64, usize)>,
+}
+
+impl Ring {
+    fn new(size: usize, vnodes: usize) -> Ring {
+        let mut points: Vec<(u64, usize)> = (0..size)
+            .flat_map(|route| {
+                (0..vnodes).map(move |v| (hash_key(&format!("{}-{}", route, v)), route))
+            })
+            .collect();
+        points.sort_unstable();
+
+        Ring { size, points }
+    }
+
+    /// the first point clockwise from the key's hash
+    fn lookup(&self, key: &str) -> usize {
+        if self.points.is_empty() {
+            return 0;
+        }
+
+        let hash = hash_key(key);
+        let idx = self.points.partition_point(|(point, _)| *point < hash);
+        self.points[idx % self.points.len()].1
+    }
+}
+
+impl ConsistentHashRouter {
+    /// create a ring with `vnodes` virtual nodes per worker
+    pub fn new(vnodes: usize) -> ConsistentHashRouter {
+        ConsistentHashRouter {
+            vnodes: vnodes.max(1),
+            rings: RwLock::new(vec![]),
+        }
+    }
+}
+
+impl Default for ConsistentHashRouter {
+    fn default() -> Self {
+        ConsistentHashRouter::new(100)
+    }
+}
+
+impl Router for ConsistentHashRouter {
+    fn route(&self, key: &str, pool: &PoolView) -> Result<usize, SupervisorError> {
+        let size = pool.size();
+        {
+            let rings = self.rings.read().unwrap_or_else(|e| e.into_inner());
+            if let Some(ring) = rings.iter().find(|ring| ring.size == size) {
+                return Ok(ring.lookup(key));
+            }
+        }
+
+        // first use or the pool has been resized
+        let mut rings = self.rings.write().unwrap_or_else(|e| e.into_inner());
+        if !rings.iter().any(|ring| ring.size == size) {
+            rings.insert(0, Ring::new(size, self.vnodes));
+            rings.truncate(RINGS);
+        }
+
+        let ring = rings.iter().find(|ring| ring.size == size);
+        Ok(ring.map_or(0, |ring| ring.lookup(key)))
+    }
+}
+
+/// the 64 bit FNV-1a hash of the key with a final mix so similar keys spread evenly; stable
+/// across runs and platforms
+pub fn hash_key(key: &str) -> u64 {
+    let mut hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// route by the hash of the key
+pub fn hash_route(key: &str, pool_size: usize) -> usize {
+    if pool_size <= 1 {
+        return 0;
+    }
+
+    (hash_key(key) % pool_size as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(router: &dyn Router, key: &str, size: usize) -> Result<usize, SupervisorError> {
+        router.route(key, &PoolView::new(size, &|_| 0))
+    }
+
+    #[test]
+    fn large_pools() {
+        let pool_size = 1_000;
+        let strict = RouteKeyRouter::strict();
+        let mut used = vec![false; pool_size];
+        for _ in 0..20_000 {
+            let key = RouteKey::create();
+            let r = route(&strict, &key, pool_size).unwrap();
+            assert_eq!(route(&RouteKeyRouter::default(), &key, pool_size), Ok(r));
+            used[r] = true;
+        }
+
+        // every worker is reachable, not just the first 256
+        assert!(used.iter().filter(|u| **u).count() > 990);
+        assert!(used[256..].iter().any(|u| *u));
+    }
+
+    #[test]
+    fn small_pools() {
+        for pool_size in [1, 2, 10, 255] {
+            let key = RouteKey::create();
+            let expect = RouteKey::parse_route(&key, pool_size as u8).unwrap() as usize;
+            assert_eq!(
+                route(&RouteKeyRouter::strict(), &key, pool_size),
+                Ok(expect)
+            );
+            assert_eq!(
+                route(&RouteKeyRouter::default(), &key, pool_size),
+                Ok(expect)
+            );
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = route(&RouteKeyRouter::strict(), "user:1234", 4).unwrap_err();
+        assert!(matches!(err, SupervisorError::Route { ref key, .. } if key == "user:1234"));
+
+        // other keys are hashed instead of all going to worker 0
+        let router = RouteKeyRouter::default();
+        let routes: Vec<usize> = (0..100)
+            .map(|n| route(&router, &format!("user:{}", n), 4).unwrap())
+            .collect();
+        for r in 0..4 {
+            assert!(routes.contains(&r));
+        }
+        assert_eq!(route(&router, "user:1", 4), Ok(hash_route("user:1", 4)));
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(hash_route("anything", 0), 0);
+        assert_eq!(hash_route("anything", 1), 0);
+        assert_eq!(hash_route("a", 1 << 20), hash_route("a", 1 << 20));
+        assert_eq!(route(&HashRouter, "a", 300), Ok(hash_route("a", 300)));
+        assert!(hash_route("a", 300) < 300);
+    }
+
+    #[test]
+    fn round_robin() {
+        let router = RoundRobinRouter::default();
+        let routes: Vec<usize> = (0..7).map(|_| route(&router, "", 3).unwrap()).collect();
+        assert_eq!(routes, vec![0, 1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn least_loaded() {
+        let router = LeastLoadedRouter::default();
+        let queues = [4, 2, 0, 2];
+        let queue_len = |route: usize| queues[route];
+        let pool = PoolView::new(4, &queue_len);
+        for _ in 0..4 {
+            assert_eq!(router.route("", &pool), Ok(2));
+        }
+
+        // an idle pool is used in turn
+        let routes: Vec<usize> = (0..4).map(|_| route(&router, "", 4).unwrap()).collect();
+        let mut sorted = routes.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn consistent_hash() {
+        let router = ConsistentHashRouter::default();
+        let keys: Vec<String> = (0..2_000).map(|n| format!("key-{}", n)).collect();
+        let before: Vec<usize> = keys
+            .iter()
+            .map(|k| route(&router, k, 10).unwrap())
+            .collect();
+
+        // stable and spread over every worker
+        for (key, r) in keys.iter().zip(before.iter()) {
+            assert_eq!(route(&router, key, 10), Ok(*r));
+        }
+        for r in 0..10 {
+            assert!(before.iter().filter(|b| **b == r).count() > 50);
+        }
+
+        // adding a worker moves roughly 1/11 of the keys, all of them to the new worker
+        let after: Vec<usize> = keys
+            .iter()
+            .map(|k| route(&router, k, 11).unwrap())
+            .collect();
+        let moved: Vec<usize> = before
+            .iter()
+            .zip(after.iter())
+            .filter(|(b, a)| b != a)
+            .map(|(_, a)| *a)
+            .collect();
+        assert!(moved.len() < keys.len() / 5);
+        assert!(moved.iter().all(|r| *r == 10));
+
+        // the rings for the old and new size are kept while both are in use
+        let points = |size: usize| {
+            let rings = router.rings.read().unwrap();
+            rings
+                .iter()
+                .find(|r| r.size == size)
+                .map(|r| r.points.as_ptr())
+        };
+        let (old, new) = (points(10), points(11));
+        for key in keys.iter().take(100) {
+            assert!(route(&router, key, 10).is_ok());
+            assert!(route(&router, key, 11).is_ok());
+        }
+        assert!(old.is_some() && new.is_some());
+        assert_eq!((points(10), points(11)), (old, new));
+        assert!(route(&router, "key", 12).is_ok());
+        assert_eq!(points(10), None);
+    }
+}
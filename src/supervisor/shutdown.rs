@@ -0,0 +1,106 @@
+/// graceful shutdown; workers answer their queued requests before they exit
+use super::{Outcome, Supervisor, SupervisorState};
+use crate::worker::WorkerTrait;
+use async_std::future;
+use async_std::task::JoinHandle;
+use futures::future::join_all;
+use log::*;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// how a worker's handler loop ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitKind {
+    /// the worker answered its queued requests and exited
+    Clean,
+    /// the handler loop returned an error or panicked
+    Failed(String),
+    /// the worker was still running at the deadline and was cancelled
+    Aborted,
+}
+
+/// the exit of the worker at a route
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerExitReport {
+    pub route: usize,
+    pub worker_id: String,
+    pub exit: ExitKind,
+}
+
+/// the result of a graceful shutdown, in route order
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub workers: Vec<WorkerExitReport>,
+}
+
+impl ShutdownReport {
+    /// return true if every worker exited cleanly before the deadline
+    pub fn is_clean(&self) -> bool {
+        self.workers.iter().all(|w| w.exit == ExitKind::Clean)
+    }
+}
+
+/// queue the shutdown command and wait until the deadline for the handler to exit
+async fn stop<W: WorkerTrait>(
+    route: usize,
+    worker: W,
+    mut handle: JoinHandle<Outcome>,
+    until: Instant,
+) -> WorkerExitReport {
+    let remaining = until.saturating_duration_since(Instant::now());
+    let drained = future::timeout(remaining, async {
+        // a closed channel means the handler has already exited
+        let _ = worker.request_channel().send(W::shutdown_command()).await;
+        (&mut handle).await
+    });
+
+    let exit = match drained.await {
+        Ok(Ok(())) => ExitKind::Clean,
+        Ok(Err(e)) => ExitKind::Failed(e),
+        Err(_) => {
+            warn!("worker id: {} did not drain in time, aborted", worker.id());
+            handle.cancel().await;
+            ExitKind::Aborted
+        }
+    };
+
+    WorkerExitReport {
+        route,
+        worker_id: worker.id(),
+        exit,
+    }
+}
+
+impl<W: WorkerTrait> Supervisor<W> {
+    /// stop accepting requests, let the workers answer their queued requests and wait up to
+    /// `deadline` for every worker to exit; workers still running after that are aborted.
+    /// Workers awaited by an earlier graceful shutdown are not reported again.
+    pub async fn shutdown_graceful(&self, deadline: Duration) -> ShutdownReport {
+        let until = Instant::now() + deadline;
+
+        // stop restarts, then turn new requests away
+        self.exit_guard.0.close();
+        self.shared.draining.store(true, Ordering::SeqCst);
+        if self.state() == SupervisorState::Running {
+            self.shared.set_state(SupervisorState::Shutdown);
+        }
+
+        let members: Vec<(usize, W, JoinHandle<Outcome>)> = self
+            .shared
+            .write_slots()
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(route, slot)| Some((route, slot.worker.clone(), slot.handle.take()?)))
+            .collect();
+
+        info!("graceful shutdown of {} workers", members.len());
+        let workers = join_all(
+            members
+                .into_iter()
+                .map(|(route, worker, handle)| stop(route, worker, handle, until)),
+        )
+        .await;
+
+        ShutdownReport { workers }
+    }
+}
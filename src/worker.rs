@@ -1,17 +1,66 @@
 /// worker support structs
 ///
+use anyhow::Result;
+use async_channel::{Receiver, Sender};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
 
 pub type JsonString = String;
 
+/// the boxed handler loop future returned by `WorkerTrait::handler`
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<()>> + Send + 'static>>;
+
+/// entries handed from one worker to another when the pool is resized.  Only the worker type that
+/// took the entries knows what is inside.  A clone is kept by the supervisor so the entries can be
+/// given back if the handoff fails.
+pub struct Handoff {
+    entries: Box<dyn Any + Send>,
+    clone: fn(&(dyn Any + Send)) -> Handoff,
+}
+
+impl Handoff {
+    pub fn new<T: Any + Send + Clone>(entries: T) -> Handoff {
+        Handoff {
+            entries: Box::new(entries),
+            clone: |entries| match entries.downcast_ref::<T>() {
+                Some(entries) => Handoff::new(entries.clone()),
+                None => unreachable!("handoff entries are always a T"),
+            },
+        }
+    }
+
+    /// return the entries if they are a `T`
+    pub fn downcast<T: Any>(self) -> Option<T> {
+        self.entries.downcast::<T>().ok().map(|entries| *entries)
+    }
+}
+
+impl Clone for Handoff {
+    fn clone(&self) -> Self {
+        (self.clone)(self.entries.as_ref())
+    }
+}
+
+impl Debug for Handoff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handoff")
+    }
+}
+
 pub const OK: &str = "Ok";
 pub const DOWN: &str = "Down";
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum WorkerState {
+    /// waiting for requests
     #[default]
     Idle,
+    /// working through a queue of requests
     Busy,
+    /// the worker's errors passed its threshold; it still responds but should be replaced
     Broken,
     Shutdown,
 }
@@ -23,6 +72,53 @@ pub struct WorkerStatus {
     pub state: WorkerState,
     pub uptime: String,
     pub error_count: u16,
+    /// the number of times the supervisor has replaced the worker in this route slot
+    #[serde(default)]
+    pub restart_count: u16,
+    /// the number of entries removed to stay within a size limit
+    #[serde(default)]
+    pub eviction_count: u64,
+    /// hit and miss counts for each level of a tiered cache
+    #[serde(default)]
+    pub tiers: Vec<TierStats>,
+    /// the number of requests waiting in the worker's queue
+    #[serde(default)]
+    pub queue_len: usize,
+    /// the number of requests the worker's queue holds; None if unbounded or unknown
+    #[serde(default)]
+    pub queue_capacity: Option<usize>,
+    /// the number of commands processed, not counting status requests
+    #[serde(default)]
+    pub command_count: u64,
+    /// the time of the last command, not counting status requests, in unix epoch milliseconds
+    #[serde(default)]
+    pub last_command_at: Option<u64>,
+}
+
+/// the hit and miss counts for one level of a tiered cache
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierStats {
+    pub tier: String,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl TierStats {
+    pub fn new(tier: &str, hits: u64, misses: u64) -> TierStats {
+        TierStats {
+            tier: tier.to_string(),
+            hits,
+            misses,
+        }
+    }
+
+    /// the fraction of reads that were hits; zero before the first read
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            reads => self.hits as f64 / reads as f64,
+        }
+    }
 }
 
 impl WorkerStatus {
@@ -39,9 +135,23 @@ impl WorkerStatus {
             state,
             uptime,
             error_count,
+            restart_count: 0,
+            eviction_count: 0,
+            tiers: vec![],
+            queue_len: 0,
+            queue_capacity: None,
+            command_count: 0,
+            last_command_at: None,
         }
     }
 
+    /// the fraction of the worker's queue in use; None if the capacity is unknown
+    pub fn queue_fill(&self) -> Option<f64> {
+        self.queue_capacity
+            .filter(|capacity| *capacity > 0)
+            .map(|capacity| self.queue_len as f64 / capacity as f64)
+    }
+
     /// return this when the comm channel is down
     pub fn worker_down(worker_id: String) -> WorkerStatus {
         WorkerStatus {
@@ -50,15 +160,120 @@ impl WorkerStatus {
             state: WorkerState::Broken,
             uptime: String::new(),
             error_count: 0,
+            restart_count: 0,
+            eviction_count: 0,
+            tiers: vec![],
+            queue_len: 0,
+            queue_capacity: None,
+            command_count: 0,
+            last_command_at: None,
         }
     }
 }
 
+/// The common interface for all workers managed by a supervisor.  A worker is a cheap, cloneable
+/// handle around the request channel; the real work is done in the handler loop that runs as a
+/// background task and reads commands from the other end of the channel.
+pub trait WorkerTrait: Clone + Send + Sync + 'static {
+    /// the request type accepted by the handler loop
+    type Command: Send + 'static;
+
+    /// worker specific options handed to each new handler loop
+    type Config: Clone + Debug + Default + Send + Sync + 'static;
+
+    /// create the worker handle from its id and the request channel's sender
+    fn create(id: String, request_tx: Sender<Self::Command>) -> Self;
+
+    /// the config handed to the worker at the route; the default gives every worker the pool's
+    /// config.  Workers that keep per route files, like the cache's append-only log, record the
+    /// route here.
+    fn route_config(config: &Self::Config, _route: usize) -> Self::Config {
+        config.clone()
+    }
+
+    /// the config for a worker that replaces a running worker when it is recycled; the default
+    /// is the route's config.  The cache uses this to skip the log replay, since the replacement
+    /// adopts the old worker's entries instead.
+    fn successor_config(config: &Self::Config, route: usize) -> Self::Config {
+        Self::route_config(config, route)
+    }
+
+    /// the handler loop; reads and responds to requests until shutdown or the channel closes
+    fn handler(id: String, config: Self::Config, rx: Receiver<Self::Command>) -> HandlerFuture;
+
+    /// the command used to request the worker's status as a json encoded `WorkerStatus`
+    fn status_command(tx: Sender<JsonString>) -> Self::Command;
+
+    /// the command used to stop the handler loop
+    fn shutdown_command() -> Self::Command;
+
+    /// return the worker's id
+    fn id(&self) -> String;
+
+    /// the channel used to send command requests to the worker
+    fn request_channel(&self) -> Sender<Self::Command>;
+
+    /// the number of seconds the worker has been running, used to recycle old workers; None if
+    /// the worker does not keep track
+    fn uptime_seconds(&self) -> Option<u64> {
+        None
+    }
+
+    /// true for workers that hold keyed state; keyed workers implement the key commands below
+    /// so their entries move when the pool is resized
+    fn is_keyed() -> bool {
+        false
+    }
+
+    /// the command that returns the keys the worker holds.  Workers without keyed state keep the
+    /// default, None, and nothing is migrated when the pool is resized.
+    fn keys_command(_tx: Sender<Vec<String>>) -> Option<Self::Command> {
+        None
+    }
+
+    /// the command that removes the keys and returns their entries for another worker
+    fn take_command(_keys: Vec<String>, _tx: Sender<Handoff>) -> Option<Self::Command> {
+        None
+    }
+
+    /// the command that stores entries taken from another worker; returns the number stored.
+    /// Keys the worker already holds are newer and are kept.
+    fn put_command(_entries: Handoff, _tx: Sender<usize>) -> Option<Self::Command> {
+        None
+    }
+
+    /// the command that copies every entry for the worker's replacement when it is recycled.  The
+    /// worker keeps its entries so it can stay in place if the replacement fails.  Workers
+    /// without state keep the default, None, and the replacement starts empty.
+    fn export_command(_tx: Sender<Handoff>) -> Option<Self::Command> {
+        None
+    }
+
+    /// the command that loads the entries exported by the recycled worker this one replaces;
+    /// returns the number stored
+    fn adopt_command(_entries: Handoff, _tx: Sender<usize>) -> Option<Self::Command> {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    //  use super::*;
+    use super::*;
+
+    #[test]
+    fn handoff() {
+        let entries = Handoff::new(vec![("a".to_string(), 1)]);
+        let copy = entries.clone();
+        assert_eq!(entries.downcast::<Vec<(String, i32)>>().unwrap().len(), 1);
+        assert!(copy.clone().downcast::<Vec<String>>().is_none());
+        assert_eq!(
+            copy.downcast::<Vec<(String, i32)>>(),
+            Some(vec![("a".to_string(), 1)])
+        );
+    }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison, clippy::assertions_on_constants)]
     fn bounded_tests() {
         async_std::task::block_on(async move {
             let (s, r) = async_channel::bounded(2);
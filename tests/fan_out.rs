@@ -0,0 +1,124 @@
+/// benchmark test for the concurrent fan-out of status, keys and len to every worker in the pool.
+/// Each worker takes a fixed delay to answer, so a sequential query is linear in the pool size
+/// while the fan-out takes about one delay.
+///
+use anyhow::Result;
+use async_channel::{Receiver, Sender};
+use async_std::task;
+use std::time::{Duration, Instant};
+use worker_lib::supervisor::Supervisor;
+use worker_lib::worker::{HandlerFuture, JsonString, WorkerState, WorkerStatus, WorkerTrait, OK};
+
+const DELAY: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone)]
+struct SlowWorker {
+    id: String,
+    request_tx: Sender<SlowCommand>,
+}
+
+enum SlowCommand {
+    Count(Sender<usize>),
+    Status(Sender<JsonString>),
+    Shutdown,
+}
+
+impl WorkerTrait for SlowWorker {
+    type Command = SlowCommand;
+    type Config = ();
+
+    fn create(id: String, request_tx: Sender<SlowCommand>) -> Self {
+        SlowWorker { id, request_tx }
+    }
+
+    fn handler(id: String, _config: (), rx: Receiver<SlowCommand>) -> HandlerFuture {
+        Box::pin(async move {
+            while let Ok(cmd) = rx.recv().await {
+                match cmd {
+                    SlowCommand::Count(tx) => {
+                        task::sleep(DELAY).await;
+                        let _ = tx.send(1).await;
+                    }
+                    SlowCommand::Status(tx) => {
+                        task::sleep(DELAY).await;
+                        let status = WorkerStatus::new(
+                            id.to_string(),
+                            OK.to_string(),
+                            WorkerState::Idle,
+                            String::new(),
+                            0,
+                        );
+                        let _ = tx.send(serde_json::to_string(&status)?).await;
+                    }
+                    SlowCommand::Shutdown => break,
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn status_command(tx: Sender<JsonString>) -> SlowCommand {
+        SlowCommand::Status(tx)
+    }
+
+    fn shutdown_command() -> SlowCommand {
+        SlowCommand::Shutdown
+    }
+
+    fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn request_channel(&self) -> Sender<SlowCommand> {
+        self.request_tx.clone()
+    }
+}
+
+#[test]
+fn fan_out_latency() -> Result<()> {
+    task::block_on(async move {
+        let pool_size = 32;
+        let supervisor: Supervisor<SlowWorker> = Supervisor::new(pool_size).await?;
+
+        // one worker at a time
+        let now = Instant::now();
+        let mut sequential = vec![];
+        for route in 0..pool_size {
+            sequential.push(supervisor.worker_status(route).await?);
+        }
+        let sequential_time = now.elapsed();
+
+        // all workers at once
+        let now = Instant::now();
+        let status = supervisor.status().await;
+        let status_time = now.elapsed();
+
+        let now = Instant::now();
+        let counts = supervisor.request_all(SlowCommand::Count).await;
+        let count_time = now.elapsed();
+
+        println!(
+            "pool: {}, sequential status: {:?}, fan-out status: {:?}, fan-out count: {:?}",
+            pool_size, sequential_time, status_time, count_time
+        );
+
+        // results are in route order
+        assert_eq!(status.len(), pool_size);
+        for (ws, expect) in status.iter().zip(sequential.iter()) {
+            assert_eq!(ws.worker_id, expect.worker_id);
+        }
+        assert_eq!(
+            counts.into_iter().filter_map(|r| r.ok()).sum::<usize>(),
+            pool_size
+        );
+
+        assert!(sequential_time >= DELAY * pool_size as u32);
+        // a generous bound; sequential requests take at least DELAY * pool_size
+        let bound = DELAY * pool_size as u32 / 2;
+        assert!(status_time < bound);
+        assert!(count_time < bound);
+
+        supervisor.shutdown().await
+    })
+}
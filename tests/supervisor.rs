@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants)]
+
 /// integration tests to ensure workers are created and respond to commands
 ///
 use domain_keys::keys::RouteKey;
@@ -36,7 +38,7 @@ fn get_usize_var(key: &str, dflt: usize) -> usize {
     let resp = env::var(key);
 
     if let Ok(sn) = resp {
-        usize::from_str_radix(&sn, 10).unwrap()
+        sn.parse::<usize>().unwrap()
     } else {
         dflt
     }
@@ -50,8 +52,8 @@ fn worker_pool() {
             .await
             .expect("should create the supervisor");
 
-        assert_eq!(supervisor.pool_size, pool_size);
-        assert_eq!(supervisor.workers.len(), pool_size);
+        assert_eq!(supervisor.pool_size(), pool_size);
+        assert_eq!(supervisor.workers().len(), pool_size);
 
         // now get the status, should be ok
         let status = supervisor.status().await;
@@ -67,7 +69,7 @@ fn worker_pool() {
         }
 
         // get the count and keyx, should be zero
-        assert_eq!(supervisor.len().await, 0);
+        assert_eq!(supervisor.len().await, Ok(0));
 
         // set a number of of values
         let set_count: usize = get_usize_var("TEST_ITEM_COUNT", 1000);
@@ -77,7 +79,7 @@ fn worker_pool() {
             let tst = TestStruct::new();
             ids.push(tst.id.to_string());
             assert_eq!(tst.id.len(), 16);
-            let route = supervisor.get_route(&tst.id);
+            let route = supervisor.get_route(&tst.id).unwrap();
             println!("{:?} {}", tst, route);
             assert!(route < pool_size);
 
@@ -90,7 +92,7 @@ fn worker_pool() {
         }
 
         assert_eq!(ids.len(), set_count);
-        assert_eq!(supervisor.len().await, set_count);
+        assert_eq!(supervisor.len().await, Ok(set_count));
 
         // now read them all back
         for id in ids.iter() {
@@ -132,7 +134,7 @@ fn worker_pool() {
         assert_eq!(tst.age, old_age);
 
         // read back the list of keys and ensure that all are in the list (count == count)
-        let list = supervisor.keys().await;
+        let list = supervisor.keys().await.expect("should return all keys");
         assert_eq!(list.len(), ids.len());
 
         // remove a few and verify the new count
@@ -144,7 +146,7 @@ fn worker_pool() {
             assert!(false);
         }
 
-        assert_eq!(supervisor.len().await, ids.len() - 1);
+        assert_eq!(supervisor.len().await, Ok(ids.len() - 1));
 
         // shut down
         assert!(supervisor.shutdown().await.is_ok());
@@ -158,8 +160,8 @@ fn single_worker() {
             .await
             .expect("should create the supervisor");
 
-        assert_eq!(supervisor.pool_size, 1);
-        assert_eq!(supervisor.workers.len(), 1);
+        assert_eq!(supervisor.pool_size(), 1);
+        assert_eq!(supervisor.workers().len(), 1);
 
         // now get the status, should be ok
         let status = supervisor.status().await;
@@ -174,7 +176,7 @@ fn single_worker() {
         }
 
         // get the count and keyx, should be zero
-        assert_eq!(supervisor.len().await, 0);
+        assert_eq!(supervisor.len().await, Ok(0));
 
         // create a sample model and set
         // verify None returned